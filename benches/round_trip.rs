@@ -0,0 +1,94 @@
+//! Round-trip latency and throughput against an in-process fake VICE.
+//!
+//! Run with `cargo bench --bench round_trip`.
+
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpListener;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use fake_vice_bin::FakeViceBin;
+use fake_vice_bin::Request;
+use fake_vice_bin::Response;
+
+const PINGS: u32 = 2000;
+const MEMORY_GETS: u32 = 200;
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// answers pings and memory gets, until the client goes away
+fn serve(listener: TcpListener) -> anyhow::Result<()> {
+	let (mut stream, _) = listener.accept()?;
+	stream.set_nodelay(true)?;
+	let memory = vec![0xea; 0x10000];
+	let mut header = [0u8; 11];
+	loop {
+		if stream.read_exact(&mut header).is_err() {
+			return Ok(());
+		}
+		let body_len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]) as usize;
+		let request_id = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+		let mut body = vec![0; body_len];
+		stream.read_exact(&mut body)?;
+
+		let response = match Request::try_from((header[10], &body[..])) {
+			Ok(Request::MemoryGet { start, end, .. }) => Response::MemoryGet {
+				bytes: memory[start as usize..=end as usize].to_vec(),
+			},
+			Ok(Request::Ping) => Response::Ping,
+			_ => Response::Invalid,
+		};
+		stream.write_all(&response.encode(request_id))?;
+	}
+}
+
+fn main() -> anyhow::Result<()> {
+	let listener = TcpListener::bind("127.0.0.1:0")?;
+	let port = listener.local_addr()?.port();
+	let server = thread::spawn(move || serve(listener));
+
+	let mut fvb = FakeViceBin::new("127.0.0.1", port)?;
+	fvb.connect()?;
+
+	let start = Instant::now();
+	for _ in 0..PINGS {
+		fvb.send_and_wait(&Request::Ping, TIMEOUT)?;
+	}
+	let pings = start.elapsed();
+
+	let memory_get = Request::MemoryGet {
+		side_effects: false,
+		start:        0x0000,
+		end:          0xffff,
+		memspace:     0,
+		bank:         0,
+	};
+	let mut bytes = 0;
+	let start = Instant::now();
+	for _ in 0..MEMORY_GETS {
+		if let Response::MemoryGet { bytes: b } = fvb.send_and_wait(&memory_get, TIMEOUT)? {
+			bytes += b.len();
+		}
+	}
+	let memory_gets = start.elapsed();
+
+	fvb.disconnect()?;
+	server
+		.join()
+		.map_err(|_| anyhow::anyhow!("Server thread panicked"))??;
+
+	println!();
+	println!(
+		"ping round trip: {:?} average over {} pings",
+		pings / PINGS,
+		PINGS
+	);
+	println!(
+		"memory get:      {:?} average, {:.1} MB/s over {} x 64KB",
+		memory_gets / MEMORY_GETS,
+		bytes as f64 / memory_gets.as_secs_f64() / (1024.0 * 1024.0),
+		MEMORY_GETS
+	);
+	Ok(())
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::packet_log;
+use crate::protocol;
+use crate::protocol::DEFAULT_CONNECT_TIMEOUT;
+use crate::protocol::EVENT_REQUEST_ID;
+use crate::protocol::MAX_BODY_LEN;
+use crate::protocol::READ_BUFFER_SIZE;
+use crate::response_framer::Framed;
+use crate::response_framer::ResponseFramer;
+use crate::DecodeError;
+use crate::ErrorCode;
+use crate::Request;
+use crate::Response;
+use crate::ResponseHeader;
+use crate::ViceError;
+
+type Answer = (ResponseHeader, Result<Response, DecodeError>);
+type Waiting = Arc<Mutex<HashMap<u32, (u8, oneshot::Sender<Answer>)>>>; // request id -> command, answer
+
+/// Async counterpart of `FakeViceBin`, for use inside a tokio runtime.
+///
+/// Method names follow `FakeViceBin`, but every request waits for its own response,
+/// unsolicited responses (e.g. `Stopped`) are collected via `next_event`.
+pub struct AsyncFakeViceBin {
+	host:            String,
+	port:            u16,
+	socket_addr:     Option<SocketAddr>, // the one connected to last
+	connect_timeout: Duration,
+	writer:          Option<OwnedWriteHalf>,
+	reader_task:     Option<JoinHandle<anyhow::Result<()>>>,
+	waiting:         Waiting,
+	events:          Option<mpsc::UnboundedReceiver<Response>>,
+	next_request_id: AtomicU32,
+}
+
+impl AsyncFakeViceBin {
+	/// `host` can be a name, an IPv4 or an IPv6 address, it is resolved in `connect()`.
+	pub fn new(host: &str, port: u16) -> Self {
+		Self {
+			host: host.to_owned(),
+			port,
+			socket_addr: None,
+			connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+			writer: None,
+			reader_task: None,
+			waiting: Arc::new(Mutex::new(HashMap::new())),
+			events: None,
+			next_request_id: AtomicU32::new(0),
+		}
+	}
+
+	/// Limit for each address tried in `connect()`.
+	pub fn set_connect_timeout(&mut self, connect_timeout: Duration) {
+		self.connect_timeout = connect_timeout;
+	}
+	/// Address of the current, or last, connection.
+	pub fn socket_addr(&self) -> Option<SocketAddr> {
+		self.socket_addr
+	}
+
+	pub fn is_connected(&self) -> bool {
+		self.reader_task
+			.as_ref()
+			.map(|t| !t.is_finished())
+			.unwrap_or(false)
+	}
+
+	pub async fn connect(&mut self) -> anyhow::Result<()> {
+		if self.writer.is_some() {
+			anyhow::bail!("Already connected!");
+		}
+		let socket_addrs = tokio::net::lookup_host((self.host.as_str(), self.port))
+			.await
+			.map_err(|e| anyhow::anyhow!("Error resolving {}:{}: {}", self.host, self.port, e))?;
+		let mut stream = None;
+		let mut errors = Vec::new();
+		for socket_addr in socket_addrs {
+			match tokio::time::timeout(self.connect_timeout, TcpStream::connect(socket_addr)).await
+			{
+				Ok(Ok(s)) => {
+					stream = Some((s, socket_addr));
+					break;
+				},
+				Ok(Err(e)) => errors.push(format!("{}: {}", socket_addr, e)),
+				Err(_) => errors.push(format!("{}: timed out", socket_addr)),
+			}
+		}
+		let Some((stream, socket_addr)) = stream else {
+			if errors.is_empty() {
+				anyhow::bail!("No address to connect to for {}:{}", self.host, self.port);
+			}
+			anyhow::bail!("Error connecting to {}", errors.join(", "));
+		};
+		self.socket_addr = Some(socket_addr);
+		stream.set_nodelay(true)?;
+		let (reader, writer) = stream.into_split();
+
+		let (events_tx, events_rx) = mpsc::unbounded_channel();
+		self.events = Some(events_rx);
+		self.writer = Some(writer);
+
+		let waiting = self.waiting.clone();
+		let socket_addr = socket_addr.to_string();
+		self.reader_task = Some(tokio::spawn(Self::read_responses(
+			reader,
+			waiting,
+			events_tx,
+			socket_addr,
+		)));
+		Ok(())
+	}
+
+	/// Closes the connection, returns the error the reader stopped with, if any.
+	pub async fn disconnect(&mut self) -> anyhow::Result<()> {
+		if let Some(mut writer) = self.writer.take() {
+			// the other side might already be gone, nothing left to report then
+			let _ = writer.shutdown().await;
+		}
+		let result = match self.reader_task.take() {
+			Some(reader_task) if reader_task.is_finished() => match reader_task.await {
+				Ok(r) => r,
+				Err(e) => Err(anyhow::anyhow!("Reader task failed: {}", e)),
+			},
+			Some(reader_task) => {
+				reader_task.abort();
+				Ok(())
+			},
+			None => Ok(()),
+		};
+		// dropping the senders fails everything still waiting
+		self.waiting.lock().unwrap().clear();
+		self.events = None;
+		result
+	}
+
+	async fn read_responses(
+		mut reader: OwnedReadHalf,
+		waiting: Waiting,
+		events: mpsc::UnboundedSender<Response>,
+		socket_addr: String,
+	) -> anyhow::Result<()> {
+		let mut framer = ResponseFramer::new(MAX_BODY_LEN);
+		let mut buf = vec![0; READ_BUFFER_SIZE];
+		loop {
+			let size = reader
+				.read(&mut buf)
+				.await
+				.map_err(|e| anyhow::anyhow!("Error reading from {}: {}", &socket_addr, e))?;
+			if size == 0 {
+				waiting.lock().unwrap().clear();
+				anyhow::bail!("Connection closed by {}", &socket_addr);
+			}
+			packet_log::log_received(&buf[..size]);
+			framer.push(&buf[..size]);
+			while let Some(framed) = framer.next() {
+				let (header, response) = match framed {
+					Framed::Response { header, response } => (header, response),
+					Framed::Dropped(dropped) => {
+						log::warn!(
+							"Invalid response header, dropped {} bytes to resync",
+							dropped
+						);
+						continue;
+					},
+				};
+				let request_id = header.request_id();
+				if request_id == EVENT_REQUEST_ID {
+					if let Ok(response) = response {
+						// nobody listening for events is fine
+						let _ = events.send(response);
+					}
+					continue;
+				}
+				let mut waiting = waiting.lock().unwrap();
+				let done = match waiting.get(&request_id) {
+					Some((command, _)) => {
+						protocol::completes_request(*command, header.response_type())
+					},
+					None => false,
+				};
+				if done {
+					if let Some((_, answer)) = waiting.remove(&request_id) {
+						let _ = answer.send((header, response));
+					}
+				}
+			}
+		}
+	}
+
+	fn generate_request_id(&self) -> u32 {
+		loop {
+			let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+			if id != EVENT_REQUEST_ID {
+				return id;
+			}
+		}
+	}
+
+	/// Sends `request` and waits for its decoded response, for as long as it takes.
+	///
+	/// Unlike `FakeViceBin::send_request`, which only sends and returns the request id.
+	pub async fn send_and_receive(&mut self, request: &Request) -> anyhow::Result<Response> {
+		let (request_id, answer_rx) = self.write_request(request).await?;
+		Self::answer(request_id, request, answer_rx.await.ok())
+	}
+
+	/// Like `send_and_receive`, but gives up after `timeout`.
+	///
+	/// Only waiting for the response is limited, the request itself is always written completely.
+	pub async fn send_and_wait(
+		&mut self,
+		request: &Request,
+		timeout: Duration,
+	) -> anyhow::Result<Response> {
+		let (request_id, answer_rx) = self.write_request(request).await?;
+		match tokio::time::timeout(timeout, answer_rx).await {
+			Ok(answer) => Self::answer(request_id, request, answer.ok()),
+			Err(_) => {
+				// a late response is dropped by the reader
+				self.waiting.lock().unwrap().remove(&request_id);
+				anyhow::bail!(
+					"Timeout waiting for response to request {:#010x} (command {:#04x})",
+					request_id,
+					request.command()
+				)
+			},
+		}
+	}
+
+	async fn write_request(
+		&mut self,
+		request: &Request,
+	) -> anyhow::Result<(u32, oneshot::Receiver<Answer>)> {
+		let request_id = self.generate_request_id();
+		let buffer = request.encode(request_id)?;
+		let (answer_tx, answer_rx) = oneshot::channel();
+		self.waiting
+			.lock()
+			.unwrap()
+			.insert(request_id, (request.command(), answer_tx));
+
+		let Some(writer) = &mut self.writer else {
+			self.waiting.lock().unwrap().remove(&request_id);
+			anyhow::bail!("Not connected to send command {:#04x}", request.command());
+		};
+		packet_log::log_sent(&buffer);
+		if let Err(e) = writer.write_all(&buffer).await {
+			self.waiting.lock().unwrap().remove(&request_id);
+			anyhow::bail!("Error writing to {}:{}: {}", self.host, self.port, e);
+		}
+		Ok((request_id, answer_rx))
+	}
+
+	// `None` if the connection was lost before the response arrived
+	fn answer(
+		request_id: u32,
+		request: &Request,
+		answer: Option<Answer>,
+	) -> anyhow::Result<Response> {
+		let Some((header, response)) = answer else {
+			anyhow::bail!(
+				"Connection lost before response to request {:#010x} (command {:#04x})",
+				request_id,
+				request.command()
+			);
+		};
+		if let Some(code) = ErrorCode::from_code(header.error_code()) {
+			return Err(ViceError::new(request_id, request.command(), code).into());
+		}
+		Ok(response?)
+	}
+
+	/// Next unsolicited response, `None` once disconnected.
+	pub async fn next_event(&mut self) -> Option<Response> {
+		match &mut self.events {
+			Some(events) => events.recv().await,
+			None => None,
+		}
+	}
+
+	pub async fn send_ping(&mut self) -> anyhow::Result<()> {
+		self.send_and_receive(&Request::Ping).await?;
+		Ok(())
+	}
+	pub async fn send_exit(&mut self) -> anyhow::Result<()> {
+		self.send_and_receive(&Request::Exit).await?;
+		Ok(())
+	}
+	pub async fn send_reset(&mut self) -> anyhow::Result<()> {
+		self.send_and_receive(&Request::Reset { kind: 0x01 })
+			.await?; // 0x01 -> hard reset
+		Ok(())
+	}
+	pub async fn read_memory(&mut self, start: u16, end: u16) -> anyhow::Result<Vec<u8>> {
+		if end < start {
+			anyhow::bail!("Invalid memory range {:#06x} - {:#06x}", start, end);
+		}
+		let request = Request::MemoryGet {
+			side_effects: false,
+			start,
+			end,
+			memspace: 0,
+			bank: 0,
+		};
+		match self.send_and_receive(&request).await? {
+			Response::MemoryGet { bytes } => Ok(bytes),
+			r => anyhow::bail!("Unexpected response {:?} to memory get", r),
+		}
+	}
+	pub async fn send_memory_set(&mut self, start: u16, bytes: &[u8]) -> anyhow::Result<()> {
+		if bytes.is_empty() || start as usize + bytes.len() > 0x10000 {
+			anyhow::bail!(
+				"Invalid memory range {:#06x} + {} bytes",
+				start,
+				bytes.len()
+			);
+		}
+		self.send_and_receive(&Request::MemorySet {
+			side_effects: false,
+			start,
+			memspace: 0,
+			bank: 0,
+			bytes: bytes.to_vec(),
+		})
+		.await?;
+		Ok(())
+	}
+	/// Register values by id, see `read_registers_available` for the names.
+	pub async fn read_registers(&mut self) -> anyhow::Result<HashMap<u8, u16>> {
+		match self
+			.send_and_receive(&Request::RegistersGet { memspace: 0 })
+			.await?
+		{
+			Response::RegistersGet { registers } => Ok(registers),
+			r => anyhow::bail!("Unexpected response {:?} to registers get", r),
+		}
+	}
+	/// Register id -> (size in bits, name).
+	pub async fn read_registers_available(&mut self) -> anyhow::Result<HashMap<u8, (u8, String)>> {
+		match self
+			.send_and_receive(&Request::RegistersAvailable { memspace: 0 })
+			.await?
+		{
+			Response::RegistersAvailable { registers } => Ok(registers),
+			r => anyhow::bail!("Unexpected response {:?} to registers available", r),
+		}
+	}
+	pub async fn send_advance_instructions(
+		&mut self,
+		count: u16,
+		step_over_subroutines: bool,
+	) -> anyhow::Result<()> {
+		self.send_and_receive(&Request::AdvanceInstructions {
+			step_over_subroutines,
+			count,
+		})
+		.await?;
+		Ok(())
+	}
+	pub async fn send_execute_until_return(&mut self) -> anyhow::Result<()> {
+		self.send_and_receive(&Request::ExecuteUntilReturn).await?;
+		Ok(())
+	}
+}
+
+impl Drop for AsyncFakeViceBin {
+	// disconnect() needs to await, so only the reader is stopped here, the socket closes with it
+	fn drop(&mut self) {
+		if let Some(reader_task) = self.reader_task.take() {
+			reader_task.abort();
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use tokio::net::TcpListener;
+
+	use super::*;
+
+	// answers pings and memory gets, sends a stopped event after each answer
+	async fn serve(listener: TcpListener) -> anyhow::Result<()> {
+		let (mut stream, _) = listener.accept().await?;
+		let mut header = [0u8; 11];
+		while stream.read_exact(&mut header).await.is_ok() {
+			let body_len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+			let request_id = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+			let mut body = vec![0; body_len as usize];
+			stream.read_exact(&mut body).await?;
+			let response = match Request::try_from((header[10], &body[..]))? {
+				Request::MemoryGet { start, end, .. } => Response::MemoryGet {
+					bytes: (start..=end).map(|a| a as u8).collect(),
+				},
+				_ => Response::Ping,
+			};
+			stream.write_all(&response.encode(request_id)).await?;
+			stream
+				.write_all(&Response::Stopped { pc: 0xc000 }.encode(EVENT_REQUEST_ID))
+				.await?;
+		}
+		Ok(())
+	}
+
+	#[tokio::test]
+	async fn round_trip() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let server = tokio::spawn(serve(listener));
+
+		let mut fvb = AsyncFakeViceBin::new("localhost", port);
+		fvb.connect().await.unwrap();
+		fvb.send_ping().await.unwrap();
+		assert_eq!(
+			fvb.read_memory(0x10, 0x13).await.unwrap(),
+			vec![0x10, 0x11, 0x12, 0x13]
+		);
+		assert_eq!(
+			fvb.next_event().await,
+			Some(Response::Stopped { pc: 0xc000 })
+		);
+		fvb.disconnect().await.unwrap();
+		assert!(!fvb.is_connected());
+		server.await.unwrap().unwrap();
+	}
+
+	#[tokio::test]
+	async fn timeout_forgets_request() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		// accepts, but never answers
+		let server = tokio::spawn(async move { listener.accept().await });
+
+		let mut fvb = AsyncFakeViceBin::new("127.0.0.1", port);
+		fvb.connect().await.unwrap();
+		assert!(fvb
+			.send_and_wait(&Request::Ping, Duration::from_millis(10))
+			.await
+			.is_err());
+		assert!(fvb.waiting.lock().unwrap().is_empty());
+		fvb.disconnect().await.unwrap();
+		drop(server);
+	}
+
+	#[tokio::test]
+	async fn drop_stops_reader() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		// keeps the connection open, so the reader would wait forever
+		let (release_tx, release_rx) = oneshot::channel::<()>();
+		let server = tokio::spawn(async move {
+			let (stream, _) = listener.accept().await.unwrap();
+			let _ = release_rx.await;
+			drop(stream);
+		});
+
+		let mut fvb = AsyncFakeViceBin::new("127.0.0.1", port);
+		fvb.connect().await.unwrap();
+		let waiting = fvb.waiting.clone();
+		drop(fvb);
+		tokio::time::timeout(Duration::from_secs(5), async {
+			// the reader holds the other reference
+			while Arc::strong_count(&waiting) > 1 {
+				tokio::task::yield_now().await;
+			}
+		})
+		.await
+		.unwrap();
+		release_tx.send(()).unwrap();
+		server.await.unwrap();
+	}
+}
@@ -0,0 +1,123 @@
+use std::ops::BitOr;
+
+/// CPU operations a checkpoint triggers on, can be combined with `|`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointOperation(u8);
+
+impl CheckpointOperation {
+	pub const LOAD: Self = Self(0x01);
+	pub const STORE: Self = Self(0x02);
+	pub const EXEC: Self = Self(0x04);
+
+	pub fn from_bits(bits: u8) -> Self {
+		Self(bits & 0x07)
+	}
+	pub fn bits(&self) -> u8 {
+		self.0
+	}
+	pub fn contains(&self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+impl BitOr for CheckpointOperation {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self {
+		Self(self.0 | rhs.0)
+	}
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+	number:        u32,
+	hit:           bool,
+	start:         u16,
+	end:           u16,
+	stop_when_hit: bool,
+	enabled:       bool,
+	operation:     CheckpointOperation,
+	temporary:     bool,
+	hit_count:     u32,
+	ignore_count:  u32,
+	has_condition: bool,
+	memspace:      u8,
+}
+
+impl Checkpoint {
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(
+		number: u32,
+		hit: bool,
+		start: u16,
+		end: u16,
+		stop_when_hit: bool,
+		enabled: bool,
+		operation: CheckpointOperation,
+		temporary: bool,
+		hit_count: u32,
+		ignore_count: u32,
+		has_condition: bool,
+		memspace: u8,
+	) -> Self {
+		Self {
+			number,
+			hit,
+			start,
+			end,
+			stop_when_hit,
+			enabled,
+			operation,
+			temporary,
+			hit_count,
+			ignore_count,
+			has_condition,
+			memspace,
+		}
+	}
+
+	pub fn set_enabled(&mut self, enabled: bool) {
+		self.enabled = enabled;
+	}
+	pub fn set_has_condition(&mut self, has_condition: bool) {
+		self.has_condition = has_condition;
+	}
+
+	pub fn number(&self) -> u32 {
+		self.number
+	}
+	/// True if this checkpoint caused the current stop.
+	pub fn hit(&self) -> bool {
+		self.hit
+	}
+	pub fn start(&self) -> u16 {
+		self.start
+	}
+	pub fn end(&self) -> u16 {
+		self.end
+	}
+	pub fn stop_when_hit(&self) -> bool {
+		self.stop_when_hit
+	}
+	pub fn enabled(&self) -> bool {
+		self.enabled
+	}
+	pub fn operation(&self) -> CheckpointOperation {
+		self.operation
+	}
+	pub fn temporary(&self) -> bool {
+		self.temporary
+	}
+	pub fn hit_count(&self) -> u32 {
+		self.hit_count
+	}
+	pub fn ignore_count(&self) -> u32 {
+		self.ignore_count
+	}
+	pub fn has_condition(&self) -> bool {
+		self.has_condition
+	}
+	pub fn memspace(&self) -> u8 {
+		self.memspace
+	}
+}
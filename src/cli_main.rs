@@ -1,5 +1,7 @@
 use clap::{Parser, Subcommand};
+use fake_vice_bin::parse::parse_number;
 use fake_vice_bin::FakeViceBin;
+use fake_vice_bin::Vsf;
 
 use crate::script::Script;
 mod script;
@@ -10,6 +12,17 @@ mod script;
 struct Cli {
 	#[command(subcommand)]
 	command: Commands,
+	/// Host running VICE with -binarymonitor, names and IPv6 addresses work too
+	#[clap(long, global = true, default_value = "127.0.0.1")]
+	host:    String,
+	#[clap(long, global = true, default_value_t = 6502)]
+	port:    u16,
+	/// More output, repeat for more detail (-vvv includes packet dumps)
+	#[clap(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+	verbose: u8,
+	/// Only report errors
+	#[clap(short, long, global = true)]
+	quiet:   bool,
 }
 
 #[derive(Subcommand)]
@@ -20,10 +33,120 @@ enum Commands {
 		dry_run: bool,
 	},
 	Demo {},
+	/// Saves the visible screen area as .png or .ppm
+	Screenshot {
+		#[clap(short, long)]
+		out: String,
+	},
+	/// Inspects a snapshot file (.vsf), no emulator needed
+	Vsf {
+		#[command(subcommand)]
+		command: VsfCommands,
+	},
+}
+
+#[derive(Subcommand)]
+enum VsfCommands {
+	/// Lists the modules
+	Info { file: String },
+	/// Prints RAM as hex, or writes it raw with --out
+	DumpMem {
+		file:  String,
+		#[clap(long, value_parser = parse_address, default_value = "0x0000")]
+		start: u16,
+		#[clap(long, value_parser = parse_address, default_value = "0xffff")]
+		end:   u16,
+		#[clap(short, long)]
+		out:   Option<String>,
+	},
+	/// Prints the CPU registers
+	Regs { file: String },
 }
 
-fn run_demo() -> anyhow::Result<()> {
-	let mut fvb = FakeViceBin::new("127.0.0.1", 6502);
+// clap wants the error as something it can display
+fn parse_address(s: &str) -> Result<u16, String> {
+	parse_number(s).map_err(|e| e.to_string())
+}
+
+fn run_vsf(command: &VsfCommands) -> anyhow::Result<()> {
+	match command {
+		VsfCommands::Info { file } => {
+			let vsf = Vsf::load(file)?;
+			print!(
+				"{} snapshot {}.{}",
+				vsf.machine(),
+				vsf.version().0,
+				vsf.version().1
+			);
+			match vsf.vice_version() {
+				Some((major, minor, micro, _)) => {
+					println!(", VICE {}.{}.{}", major, minor, micro)
+				},
+				None => println!(),
+			}
+			for m in vsf.modules() {
+				println!(
+					"{:16} {}.{} {:7} bytes",
+					m.name(),
+					m.version().0,
+					m.version().1,
+					m.data().len()
+				);
+			}
+		},
+		VsfCommands::DumpMem {
+			file,
+			start,
+			end,
+			out,
+		} => {
+			if end < start {
+				anyhow::bail!("Invalid memory range {:#06x} - {:#06x}", start, end);
+			}
+			let vsf = Vsf::load(file)?;
+			let ram = &vsf.ram()?[*start as usize..=*end as usize];
+			match out {
+				Some(out) => std::fs::write(out, ram)
+					.map_err(|e| anyhow::anyhow!("Error writing {}: {}", out, e))?,
+				None => {
+					for (i, chunk) in ram.chunks(16).enumerate() {
+						print!("{:04x}:", *start as usize + i * 16);
+						for b in chunk.iter() {
+							print!(" {:02x}", b);
+						}
+						println!();
+					}
+				},
+			}
+		},
+		VsfCommands::Regs { file } => {
+			let r = Vsf::load(file)?.registers()?;
+			println!(
+				"PC {:04x}  A {:02x}  X {:02x}  Y {:02x}  SP {:02x}  NV-BDIZC {:08b}  clock {}",
+				r.pc(),
+				r.a(),
+				r.x(),
+				r.y(),
+				r.sp(),
+				r.status(),
+				r.clock()
+			);
+		},
+	}
+	Ok(())
+}
+
+fn run_screenshot(host: &str, port: u16, out: &str) -> anyhow::Result<()> {
+	let mut fvb = FakeViceBin::new(host, port)?;
+	fvb.connect()?;
+	fvb.screenshot(std::time::Duration::from_secs(5))?
+		.save(out)?;
+	log::info!("Saved screenshot {}", out);
+	fvb.disconnect()
+}
+
+fn run_demo(host: &str, port: u16) -> anyhow::Result<()> {
+	let mut fvb = FakeViceBin::new(host, port)?;
 
 	let short_delay = std::time::Duration::from_millis(5);
 	let delay = std::time::Duration::from_millis(500);
@@ -32,7 +155,7 @@ fn run_demo() -> anyhow::Result<()> {
 	fvb.connect()?;
 	fvb.send_registers_available(0)?;
 
-	println!("Reset");
+	log::info!("Reset");
 	std::thread::sleep(delay);
 	//	fvb.connect()?;
 	fvb.update()?;
@@ -44,14 +167,14 @@ fn run_demo() -> anyhow::Result<()> {
 		fvb.update()?;
 	}
 
-	println!("Exit");
+	log::info!("Exit");
 	std::thread::sleep(delay);
 	//	fvb.connect()?;
 	fvb.update()?;
 	fvb.send_exit()?;
 	//	fvb.disconnect()?;
 
-	println!("Load");
+	log::info!("Load");
 	std::thread::sleep(delay);
 	//	fvb.connect()?;
 	fvb.send_load("main.prg", true)?;
@@ -69,34 +192,40 @@ fn run_demo() -> anyhow::Result<()> {
 	*/
 	//	fvb.connect()?;
 	loop {
-		fvb.send_advance_instructions(1000)?;
+		fvb.send_advance_instructions(1000, false)?;
 		//fvb.send_ping()?;
 		//fvb.send_exit()?;
 		fvb.update()?;
 		std::thread::sleep(short_delay);
 	}
-	loop {
-		fvb.update()?;
-		//fvb.send_ping()?;
-		fvb.send_exit()?;
-		std::thread::sleep(delay);
-	}
-
-	Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
 	let cli = Cli::parse();
+	let level = match (cli.quiet, cli.verbose) {
+		(true, _) => log::LevelFilter::Error,
+		(false, 0) => log::LevelFilter::Warn,
+		(false, 1) => log::LevelFilter::Info,
+		(false, 2) => log::LevelFilter::Debug,
+		(false, _) => log::LevelFilter::Trace,
+	};
+	// RUST_LOG still wins, e.g. RUST_LOG=fake_vice_bin::packets=trace
+	env_logger::Builder::new()
+		.filter_level(level)
+		.parse_default_env()
+		.init();
 	match &cli.command {
 		Commands::Script { file, dry_run } => {
 			let mut script = Script::new();
 			script.load(file)?;
-			println!("Script: {:#?}", &script);
+			log::debug!("Script: {:#?}", &script);
 			if !dry_run {
-				script.run()?;
+				script.run(&cli.host, cli.port)?;
 			}
 			Ok(())
 		},
-		Commands::Demo {} => run_demo(),
+		Commands::Demo {} => run_demo(&cli.host, cli.port),
+		Commands::Screenshot { out } => run_screenshot(&cli.host, cli.port, out),
+		Commands::Vsf { command } => run_vsf(command),
 	}
 }
@@ -0,0 +1,246 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::parse::find_top_level;
+use crate::parse::parse_number;
+
+// 6502 registers, plus raster line and cycle, as known to the VICE monitor
+const REGISTERS: [&str; 7] = ["A", "X", "Y", "PC", "SP", "LIN", "CYC"];
+
+/// Value side of a VICE checkpoint condition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+	Register(String),
+	Value(u16),
+	Memory(u16),
+}
+
+impl Operand {
+	pub fn register(name: &str) -> Self {
+		Operand::Register(name.to_uppercase())
+	}
+	pub fn value(value: u16) -> Self {
+		Operand::Value(value)
+	}
+	pub fn memory(address: u16) -> Self {
+		Operand::Memory(address)
+	}
+
+	pub fn eq(self, rhs: impl Into<Operand>) -> ConditionExpr {
+		ConditionExpr::compare(self, CompareOp::Eq, rhs.into())
+	}
+	pub fn ne(self, rhs: impl Into<Operand>) -> ConditionExpr {
+		ConditionExpr::compare(self, CompareOp::Ne, rhs.into())
+	}
+	pub fn lt(self, rhs: impl Into<Operand>) -> ConditionExpr {
+		ConditionExpr::compare(self, CompareOp::Lt, rhs.into())
+	}
+	pub fn gt(self, rhs: impl Into<Operand>) -> ConditionExpr {
+		ConditionExpr::compare(self, CompareOp::Gt, rhs.into())
+	}
+	pub fn le(self, rhs: impl Into<Operand>) -> ConditionExpr {
+		ConditionExpr::compare(self, CompareOp::Le, rhs.into())
+	}
+	pub fn ge(self, rhs: impl Into<Operand>) -> ConditionExpr {
+		ConditionExpr::compare(self, CompareOp::Ge, rhs.into())
+	}
+}
+
+impl From<u16> for Operand {
+	fn from(value: u16) -> Self {
+		Operand::Value(value)
+	}
+}
+
+impl fmt::Display for Operand {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Operand::Register(name) => write!(f, "{}", name),
+			Operand::Value(value) => write!(f, "${:x}", value),
+			Operand::Memory(address) => write!(f, "@cpu:${:04x}", address),
+		}
+	}
+}
+
+impl FromStr for Operand {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		let s = s.trim();
+		if let Some(address) = s.strip_prefix('@') {
+			let address = address.strip_prefix("cpu:").unwrap_or(address);
+			return Ok(Operand::Memory(parse_number(address)?));
+		}
+		if s.starts_with(|c: char| c.is_ascii_alphabetic()) {
+			let name = s.to_uppercase();
+			if REGISTERS.contains(&name.as_str()) {
+				return Ok(Operand::Register(name));
+			}
+			anyhow::bail!(
+				"Unknown register >{}<, hex numbers need a $ or 0x prefix",
+				s
+			);
+		}
+		Ok(Operand::Value(parse_number(s)?))
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+	Eq,
+	Ne,
+	Lt,
+	Gt,
+	Le,
+	Ge,
+}
+
+impl CompareOp {
+	// longest first, so `<=` is not taken for `<`
+	const ALL: [(&'static str, CompareOp); 6] = [
+		("==", CompareOp::Eq),
+		("!=", CompareOp::Ne),
+		("<=", CompareOp::Le),
+		(">=", CompareOp::Ge),
+		("<", CompareOp::Lt),
+		(">", CompareOp::Gt),
+	];
+
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			CompareOp::Eq => "==",
+			CompareOp::Ne => "!=",
+			CompareOp::Lt => "<",
+			CompareOp::Gt => ">",
+			CompareOp::Le => "<=",
+			CompareOp::Ge => ">=",
+		}
+	}
+}
+
+/// Checkpoint condition, serialises to VICE's condition syntax via `Display`.
+///
+/// `Operand::register("A").eq(0x40).and(Operand::register("X").gt(3))`
+/// becomes `A == $40 && X > $3`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionExpr {
+	Compare {
+		left:  Operand,
+		op:    CompareOp,
+		right: Operand,
+	},
+	And {
+		left:  Box<ConditionExpr>,
+		right: Box<ConditionExpr>,
+	},
+	Or {
+		left:  Box<ConditionExpr>,
+		right: Box<ConditionExpr>,
+	},
+}
+
+impl ConditionExpr {
+	pub fn compare(left: Operand, op: CompareOp, right: Operand) -> Self {
+		ConditionExpr::Compare { left, op, right }
+	}
+	pub fn and(self, other: ConditionExpr) -> Self {
+		ConditionExpr::And {
+			left:  Box::new(self),
+			right: Box::new(other),
+		}
+	}
+	pub fn or(self, other: ConditionExpr) -> Self {
+		ConditionExpr::Or {
+			left:  Box::new(self),
+			right: Box::new(other),
+		}
+	}
+
+	fn fmt_nested(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ConditionExpr::Compare { .. } => write!(f, "{}", self),
+			_ => write!(f, "({})", self),
+		}
+	}
+}
+
+impl fmt::Display for ConditionExpr {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ConditionExpr::Compare { left, op, right } => {
+				write!(f, "{} {} {}", left, op.as_str(), right)
+			},
+			ConditionExpr::And { left, right } => {
+				left.fmt_nested(f)?;
+				write!(f, " && ")?;
+				right.fmt_nested(f)
+			},
+			ConditionExpr::Or { left, right } => {
+				left.fmt_nested(f)?;
+				write!(f, " || ")?;
+				right.fmt_nested(f)
+			},
+		}
+	}
+}
+
+impl FromStr for ConditionExpr {
+	type Err = anyhow::Error;
+
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		let s = s.trim();
+		if let Some(i) = find_top_level(s, "||") {
+			let left = s[..i].parse::<ConditionExpr>()?;
+			let right = s[i + 2..].parse::<ConditionExpr>()?;
+			return Ok(left.or(right));
+		}
+		if let Some(i) = find_top_level(s, "&&") {
+			let left = s[..i].parse::<ConditionExpr>()?;
+			let right = s[i + 2..].parse::<ConditionExpr>()?;
+			return Ok(left.and(right));
+		}
+		if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+			return inner.parse();
+		}
+		for (token, op) in CompareOp::ALL.iter() {
+			if let Some((left, right)) = s.split_once(token) {
+				return Ok(ConditionExpr::compare(left.parse()?, *op, right.parse()?));
+			}
+		}
+		anyhow::bail!("Invalid condition >{}<", s);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn operands() {
+		assert_eq!(
+			"pc".parse::<Operand>().unwrap(),
+			Operand::Register("PC".to_owned())
+		);
+		assert_eq!("$c000".parse::<Operand>().unwrap(), Operand::Value(0xc000));
+		assert_eq!("49152".parse::<Operand>().unwrap(), Operand::Value(0xc000));
+		assert_eq!(
+			"@cpu:$d020".parse::<Operand>().unwrap(),
+			Operand::Memory(0xd020)
+		);
+	}
+
+	#[test]
+	fn unprefixed_hex_is_rejected() {
+		assert!("c000".parse::<Operand>().is_err());
+		assert!("c000 == 1".parse::<ConditionExpr>().is_err());
+		assert!("ff".parse::<Operand>().is_err());
+	}
+
+	#[test]
+	fn display_round_trip() {
+		let c = "A == $10 && (X < 3 || @$d012 >= $80)"
+			.parse::<ConditionExpr>()
+			.unwrap();
+		assert_eq!(c.to_string().parse::<ConditionExpr>().unwrap(), c);
+	}
+}
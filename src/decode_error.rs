@@ -0,0 +1,30 @@
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+	Truncated { needed: usize, available: usize },
+	UnknownType(u8),
+	BadUtf8,
+	CountMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			DecodeError::Truncated { needed, available } => {
+				write!(
+					f,
+					"Truncated body, needed {} bytes, got {}",
+					needed, available
+				)
+			},
+			DecodeError::UnknownType(t) => write!(f, "Unknown type {:#04x}", t),
+			DecodeError::BadUtf8 => write!(f, "Invalid utf8 in string"),
+			DecodeError::CountMismatch { expected, found } => {
+				write!(f, "Count mismatch, expected {} got {}", expected, found)
+			},
+		}
+	}
+}
+
+impl std::error::Error for DecodeError {}
@@ -0,0 +1,10 @@
+use crate::Response;
+
+/// Something that happened without being asked for, collected via `FakeViceBin::pop_event`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+	/// Unsolicited response from VICE, e.g. `Stopped` or `Resumed`.
+	Response(Response),
+	/// The connection was lost and re-established, recorded state has been re-sent.
+	Reconnected { attempts: u32 },
+}
@@ -1,20 +1,45 @@
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
 use std::io::Read;
 use std::io::Write;
-use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::net::TcpStream;
-use std::str::FromStr;
+use std::net::ToSocketAddrs;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
 use std::sync::Arc;
 use std::thread;
+use std::thread::JoinHandle;
 use std::time::Duration;
+use std::time::Instant;
 
-use ringbuf::consumer::Consumer;
-use ringbuf::producer::Producer;
-use ringbuf::HeapRb;
-
+use crate::packet_log;
+use crate::protocol;
+use crate::protocol::DEFAULT_CONNECT_TIMEOUT;
+use crate::protocol::EVENT_REQUEST_ID;
+use crate::protocol::MAX_BODY_LEN;
+use crate::protocol::READ_BUFFER_SIZE;
+use crate::response_framer::Framed;
+use crate::response_framer::ResponseFramer;
+use crate::screen_text;
+use crate::to_petscii;
+use crate::Checkpoint;
+use crate::CheckpointOperation;
+use crate::ConditionExpr;
+use crate::DecodeError;
+use crate::ErrorCode;
+use crate::Event;
+use crate::JoystickState;
+use crate::ReconnectPolicy;
+use crate::Request;
 use crate::Response;
 use crate::ResponseHeader;
+use crate::Screenshot;
+use crate::ViceError;
 
 #[derive(Debug, Default)]
 pub struct Register {
@@ -44,164 +69,260 @@ impl Register {
 	}
 }
 
+/// Called with the program counter.
+pub type PcCallback = Box<dyn FnMut(u16) + Send>;
+/// Called with the checkpoint that stopped the CPU, and the program counter.
+pub type CheckpointHitCallback = Box<dyn FnMut(&Checkpoint, u16) + Send>;
+
+const MAX_QUEUED_EVENTS: usize = 1024;
+/// Upper bound for blocking on the I/O thread, so a lost connection is noticed.
+const MAX_RESPONSE_WAIT: Duration = Duration::from_millis(10);
+
 //#[derive(Debug)]
 pub struct FakeViceBin {
-	socket_addr:     SocketAddr,
-	//stream:          Option<TcpStream>,
-	resets_pending:  usize,
-	load_pending:    bool,
+	socket_addrs: Vec<SocketAddr>,
+	socket_addr: SocketAddr, // the one connected to last
+	connect_timeout: Duration,
+	stream: Option<TcpStream>,
+	resets_pending: usize,
+	load_pending: bool,
 	next_request_id: u32,
-	running:         bool,
+	pending_requests: HashMap<u32, u8>, // request id -> command
+	waiting_requests: HashSet<u32>,
+	completed_requests: HashMap<u32, (u8, Result<Response, DecodeError>)>, // request id -> error code, response
+	events: VecDeque<Event>,
+	request_errors: VecDeque<ViceError>,
+	dropped_bytes: usize,
+	running: bool,
 	program_counter: u16,
-	registers:       HashMap<u8, Register>,
+	stop_count: u32, // stopped and jam events seen, to notice the stop following a step
+	registers: HashMap<u8, Register>,
+	memory_gets_pending: VecDeque<u16>,
+	memory_start: u16,
+	memory: Vec<u8>,
+	checkpoints: HashMap<u32, Checkpoint>,
+	checkpoint_deletes_pending: VecDeque<u32>,
+	checkpoint_toggles_pending: VecDeque<(u32, bool)>,
+	checkpoint_lists_pending: usize,
+	checkpoint_conditions_pending: HashMap<u32, String>, // request id -> condition
+	condition_sets_pending: VecDeque<(u32, String)>,
+	checkpoint_conditions: HashMap<u32, String>, // checkpoint number -> condition
+	registers_available_memspaces: Vec<u8>,
+	reconnect_policy: Option<ReconnectPolicy>,
+	reconnect_count: u32,
+	stopped_callbacks: Vec<PcCallback>,
+	resumed_callbacks: Vec<PcCallback>,
+	jam_callbacks: Vec<PcCallback>,
+	checkpoint_hit_callbacks: Vec<CheckpointHitCallback>,
+	checkpoints_listed: Vec<u32>,
+	checkpoint_hit: Option<u32>,
+	stopped_by_checkpoint: Option<u32>,
 
-	response_rb_prod: Option<Producer<u8, Arc<HeapRb<u8>>>>,
-	response_rb_cons: Option<Consumer<u8, Arc<HeapRb<u8>>>>,
-	request_rb_prod:  Option<Producer<u8, Arc<HeapRb<u8>>>>,
-	request_rb_cons:  Option<Consumer<u8, Arc<HeapRb<u8>>>>,
-	connected:        bool,
+	response_rx:    Option<Receiver<Framed>>,
+	connected:      bool,
+	stop_requested: Arc<AtomicBool>,
+	io_thread:      Option<JoinHandle<anyhow::Result<()>>>,
 }
 
 impl FakeViceBin {
-	pub fn new(host: &str, port: u16) -> Self {
-		let ip: IpAddr = IpAddr::from_str(host).expect("...");
-		Self {
-			socket_addr:      (ip, port).into(),
-			//stream:           None,
+	/// `host` can be a name, an IPv4 or an IPv6 address.
+	pub fn new(host: &str, port: u16) -> anyhow::Result<Self> {
+		let socket_addrs = (host, port)
+			.to_socket_addrs()
+			.map_err(|e| anyhow::anyhow!("Error resolving {}:{}: {}", host, port, e))?
+			.collect();
+		Self::from_socket_addrs(socket_addrs)
+	}
+	/// Accepts anything `ToSocketAddrs` does, e.g. `"localhost:6502"` or `"[::1]:6502"`.
+	pub fn with_addrs(addrs: impl ToSocketAddrs) -> anyhow::Result<Self> {
+		let socket_addrs = addrs
+			.to_socket_addrs()
+			.map_err(|e| anyhow::anyhow!("Error resolving address: {}", e))?
+			.collect();
+		Self::from_socket_addrs(socket_addrs)
+	}
+	fn from_socket_addrs(socket_addrs: Vec<SocketAddr>) -> anyhow::Result<Self> {
+		let Some(socket_addr) = socket_addrs.first().copied() else {
+			anyhow::bail!("No address to connect to");
+		};
+		Ok(Self {
+			socket_addrs,
+			socket_addr,
+			connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+			stream: None,
 			//response_buffer: VecDeque::new(),
-			resets_pending:   0,
-			load_pending:     false,
-			next_request_id:  0,
-			running:          true,
-			program_counter:  0,
-			registers:        HashMap::default(),
-			response_rb_prod: None, //prod,
-			response_rb_cons: None, //cons,
-			request_rb_prod:  None,
-			request_rb_cons:  None,
-			connected:        false,
+			resets_pending: 0,
+			load_pending: false,
+			next_request_id: 0,
+			pending_requests: HashMap::default(),
+			waiting_requests: HashSet::default(),
+			completed_requests: HashMap::default(),
+			events: VecDeque::new(),
+			request_errors: VecDeque::new(),
+			dropped_bytes: 0,
+			running: true,
+			program_counter: 0,
+			stop_count: 0,
+			registers: HashMap::default(),
+			memory_gets_pending: VecDeque::new(),
+			memory_start: 0,
+			memory: Vec::new(),
+			checkpoints: HashMap::default(),
+			checkpoint_deletes_pending: VecDeque::new(),
+			checkpoint_toggles_pending: VecDeque::new(),
+			checkpoint_lists_pending: 0,
+			checkpoint_conditions_pending: HashMap::default(),
+			condition_sets_pending: VecDeque::new(),
+			checkpoint_conditions: HashMap::default(),
+			registers_available_memspaces: Vec::new(),
+			reconnect_policy: None,
+			reconnect_count: 0,
+			stopped_callbacks: Vec::new(),
+			resumed_callbacks: Vec::new(),
+			jam_callbacks: Vec::new(),
+			checkpoint_hit_callbacks: Vec::new(),
+			checkpoints_listed: Vec::new(),
+			checkpoint_hit: None,
+			stopped_by_checkpoint: None,
+			response_rx: None,
+			connected: false,
+			stop_requested: Arc::new(AtomicBool::new(false)),
+			io_thread: None,
+		})
+	}
+
+	/// Limit for each address tried in `connect()`.
+	pub fn set_connect_timeout(&mut self, connect_timeout: Duration) {
+		self.connect_timeout = connect_timeout;
+	}
+	pub fn connect_timeout(&self) -> Duration {
+		self.connect_timeout
+	}
+	/// Address of the current, or last, connection.
+	pub fn socket_addr(&self) -> SocketAddr {
+		self.socket_addr
+	}
+
+	// tries all resolved addresses in order
+	fn open_stream(&mut self) -> anyhow::Result<TcpStream> {
+		let mut errors = Vec::new();
+		for socket_addr in self.socket_addrs.iter() {
+			match TcpStream::connect_timeout(socket_addr, self.connect_timeout) {
+				Ok(stream) => {
+					self.socket_addr = *socket_addr;
+					return Ok(stream);
+				},
+				Err(e) => errors.push(format!("{}: {}", socket_addr, e)),
+			}
 		}
+		anyhow::bail!("Error connecting to {}", errors.join(", "));
 	}
 
+	/// False once disconnected, or when the I/O thread stopped on its own, e.g. because VICE went away.
 	pub fn is_connected(&self) -> bool {
 		self.connected
+			&& self
+				.io_thread
+				.as_ref()
+				.map(|t| !t.is_finished())
+				.unwrap_or(false)
 	}
 
 	pub fn connect(&mut self) -> anyhow::Result<()> {
 		if self.connected {
 			anyhow::bail!("Already connected!");
 		}
-		match TcpStream::connect(self.socket_addr) {
-			Ok(mut stream) => {
-				stream.set_nonblocking(true)?;
-				//stream.set_nodelay(true)?; // maybe not
-
-				let rb = HeapRb::<u8>::new(64 * 1024); // this should be more than plenty, well, it's too large, but I have a plan
-				let (prod, cons) = rb.split();
-				self.response_rb_prod = Some(prod);
-				self.response_rb_cons = Some(cons);
+		match self.open_stream() {
+			Ok(stream) => {
+				// requests are small, don't let them sit in Nagle's buffer
+				stream.set_nodelay(true)?;
+				let mut read_stream = stream.try_clone()?;
+				self.stream = Some(stream);
 
-				let rb = HeapRb::<u8>::new(64 * 1024); // this should be more than plenty, well, it's too large, but I have a plan
-				let (prod, cons) = rb.split();
-				self.request_rb_prod = Some(prod);
-				self.request_rb_cons = Some(cons);
+				// responses are framed and decoded on the I/O thread
+				let (response_tx, response_rx) = mpsc::channel();
+				self.response_rx = Some(response_rx);
 
 				self.connected = true;
 
-				let mut response_rb_prod = self.response_rb_prod.take();
-				let mut request_rb_cons = self.request_rb_cons.take();
+				self.stop_requested.store(false, Ordering::Relaxed);
+				let stop_requested = self.stop_requested.clone();
 
 				let socket_addr = self.socket_addr.to_string();
-				thread::spawn(move || -> anyhow::Result<()> {
-					let delay = std::time::Duration::from_millis(5);
+				let io_thread = thread::spawn(move || -> anyhow::Result<()> {
+					let mut framer = ResponseFramer::new(MAX_BODY_LEN);
+					let mut buf = vec![0; READ_BUFFER_SIZE];
 					loop {
-						// receive
-						let mut buf = [0; 1];
-						loop {
-							let _size = match stream.read(&mut buf) {
-								Ok(size) => {
-									//println!("Read {} bytes from stream", size);
-									size
-								},
-								Err(ref e) => {
-									match e.kind() {
-										std::io::ErrorKind::WouldBlock => {
-											//println!("No updates");
-											break;
-										},
-										e => {
-											anyhow::bail!(
-												"Error reading from to {}: {}",
-												&socket_addr,
-												e
-											);
-										},
-									}
-								},
-							};
-
-							if let Some(response_rb_prod) = &mut response_rb_prod {
-								while response_rb_prod.free_len() < buf.len() {
-									// spin until there is space
-									print!(".");
-									let short_delay = std::time::Duration::from_millis(1);
-									std::thread::sleep(short_delay);
-								}
-								let l = response_rb_prod.push_slice(&buf);
-								/*
-								for b in buf.iter() {
-									match response_rb_prod.push(*b) {
-										Ok(()) => {},
-										Err(e) => {
-											anyhow::bail!("Error storing response {}", e);
-										},
-									}
+						// blocks until data arrives, disconnect() wakes us up by shutting down the socket
+						let size = match read_stream.read(&mut buf) {
+							Ok(size) => size,
+							Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+							Err(e) => {
+								if stop_requested.load(Ordering::Relaxed) {
+									return Ok(());
 								}
-								*/
+								anyhow::bail!("Error reading from {}: {}", &socket_addr, e);
+							},
+						};
+						if size == 0 {
+							if stop_requested.load(Ordering::Relaxed) {
+								return Ok(());
 							}
+							anyhow::bail!("Connection closed by {}", &socket_addr);
 						}
-						// println!("Read {} bytes in update", self.response_buffer.len() );
-
-						// send
-						//stream.write(buffer)?;
-						if let Some(request_rb_cons) = &mut request_rb_cons {
-							let len = request_rb_cons.len();
-							if len > 0 {
-								let mut buffer_vec = Vec::with_capacity(len);
-								buffer_vec.resize(len, 0);
-								let mut buffer = &mut buffer_vec[0..len];
-								let l = request_rb_cons.pop_slice(&mut buffer);
-								println!("Got {} bytes from ringbuffer for sending", l);
-								stream.write(buffer)?;
+
+						packet_log::log_received(&buf[..size]);
+						framer.push(&buf[..size]);
+						while let Some(framed) = framer.next() {
+							if response_tx.send(framed).is_err() {
+								// nobody is listening anymore
+								return Ok(());
 							}
-						};
-						thread::sleep(delay);
+						}
 					}
-					Ok(())
 				});
+				self.io_thread = Some(io_thread);
 				Ok(())
 			},
-			Err(e) => {
-				anyhow::bail!("Error connecting to {}: {}", &self.socket_addr, e);
-			},
+			Err(e) => Err(e),
 		}
 	}
+	/// Stops and joins the I/O thread, closing the connection.
+	///
+	/// Returns the error the I/O thread stopped with, if any.
+	/// Afterwards `connect()` can be called again.
 	pub fn disconnect(&mut self) -> anyhow::Result<()> {
-		Ok(())
+		if !self.connected {
+			return Ok(());
+		}
+		self.stop_requested.store(true, Ordering::Relaxed);
+		if let Some(stream) = self.stream.take() {
+			// the other side might already be gone, nothing left to report then
+			let _ = stream.shutdown(std::net::Shutdown::Both);
+		}
+		let result = match self.io_thread.take() {
+			Some(io_thread) => match io_thread.join() {
+				Ok(r) => r,
+				Err(_) => Err(anyhow::anyhow!("Connection thread panicked")),
+			},
+			None => Ok(()),
+		};
 
-		// :TODO:
-		/*
-		if let Some(stream) = &mut self.stream.take() {
-			stream.shutdown(std::net::Shutdown::Both)?;
-			self.response_rb_prod = None;
-			self.response_rb_cons = None;
+		self.response_rx = None;
+		self.connected = false;
 
-			self.connected = false;
-			Ok(())
-		} else {
-			Ok(())
-		}
-		*/
+		// nothing in flight will be answered anymore
+		self.pending_requests.clear();
+		self.waiting_requests.clear();
+		self.checkpoint_conditions_pending.clear();
+		self.memory_gets_pending.clear();
+		self.checkpoint_deletes_pending.clear();
+		self.checkpoint_toggles_pending.clear();
+		self.checkpoint_lists_pending = 0;
+		self.condition_sets_pending.clear();
+		self.resets_pending = 0;
+
+		result
 	}
 
 	pub fn is_reset_pending(&self) -> bool {
@@ -210,172 +331,450 @@ impl FakeViceBin {
 	pub fn is_load_pending(&self) -> bool {
 		self.load_pending
 	}
+	pub fn is_memory_get_pending(&self) -> bool {
+		!self.memory_gets_pending.is_empty()
+	}
+
+	/// Start address of the bytes returned by the last memory get.
+	pub fn memory_start(&self) -> u16 {
+		self.memory_start
+	}
+	/// Bytes returned by the last memory get.
+	pub fn memory(&self) -> &[u8] {
+		&self.memory
+	}
 
-	fn generate_request_id(&mut self) -> [u8; 4] {
+	/// Looks up a register id by name, needs the response to `send_registers_available`.
+	pub fn register_id(&self, name: &str) -> Option<u8> {
+		self.registers
+			.iter()
+			.find(|(_, r)| r.name().eq_ignore_ascii_case(name))
+			.map(|(id, _)| *id)
+	}
+	/// Last known value of the register called `name`.
+	pub fn register_value(&self, name: &str) -> Option<u16> {
+		self.register_id(name)
+			.and_then(|id| self.registers.get(&id))
+			.map(|r| r.value())
+	}
+
+	/// Checkpoints currently known to be set, by checkpoint number.
+	pub fn checkpoints(&self) -> &HashMap<u32, Checkpoint> {
+		&self.checkpoints
+	}
+	/// False while the CPU is stopped in the monitor, or jammed.
+	pub fn is_running(&self) -> bool {
+		self.running
+	}
+	/// Program counter as of the last stop, resume, or jam.
+	pub fn pc(&self) -> u16 {
+		self.program_counter
+	}
+
+	/// Callbacks run from `update()` and the blocking waits, in the order they were added.
+	pub fn on_stopped(&mut self, callback: impl FnMut(u16) + Send + 'static) {
+		self.stopped_callbacks.push(Box::new(callback));
+	}
+	pub fn on_resumed(&mut self, callback: impl FnMut(u16) + Send + 'static) {
+		self.resumed_callbacks.push(Box::new(callback));
+	}
+	pub fn on_jam(&mut self, callback: impl FnMut(u16) + Send + 'static) {
+		self.jam_callbacks.push(Box::new(callback));
+	}
+	/// Runs before the matching `on_stopped` callbacks.
+	pub fn on_checkpoint_hit(&mut self, callback: impl FnMut(&Checkpoint, u16) + Send + 'static) {
+		self.checkpoint_hit_callbacks.push(Box::new(callback));
+	}
+	pub fn clear_callbacks(&mut self) {
+		self.stopped_callbacks.clear();
+		self.resumed_callbacks.clear();
+		self.jam_callbacks.clear();
+		self.checkpoint_hit_callbacks.clear();
+	}
+
+	/// Number of the checkpoint that caused the last stop, if any.
+	pub fn stopped_by_checkpoint(&self) -> Option<u32> {
+		self.stopped_by_checkpoint
+	}
+
+	/// Bytes dropped while resynchronising after corrupted responses.
+	pub fn dropped_bytes(&self) -> usize {
+		self.dropped_bytes
+	}
+	/// Number of requests that have not been answered yet.
+	pub fn pending_request_count(&self) -> usize {
+		self.pending_requests.len()
+	}
+	/// Takes the oldest error for a request nobody waited on.
+	pub fn pop_request_error(&mut self) -> Option<ViceError> {
+		self.request_errors.pop_front()
+	}
+	/// Takes the oldest unsolicited event (e.g. `Stopped` or a checkpoint hit).
+	pub fn pop_event(&mut self) -> Option<Event> {
+		self.events.pop_front()
+	}
+	fn push_event(&mut self, event: Event) {
+		if self.events.len() >= MAX_QUEUED_EVENTS {
+			self.events.pop_front();
+		}
+		self.events.push_back(event);
+	}
+
+	/// Opt-in, without a policy a lost connection is reported as an error.
+	pub fn set_reconnect_policy(&mut self, reconnect_policy: Option<ReconnectPolicy>) {
+		self.reconnect_policy = reconnect_policy;
+	}
+	pub fn reconnect_policy(&self) -> Option<&ReconnectPolicy> {
+		self.reconnect_policy.as_ref()
+	}
+	/// Number of successful automatic reconnects.
+	pub fn reconnect_count(&self) -> u32 {
+		self.reconnect_count
+	}
+
+	fn generate_request_id(&mut self) -> u32 {
 		let id = self.next_request_id;
-		self.next_request_id += 1;
-		[
-			(id >> 0 & 0xff) as u8,
-			(id >> 1 & 0xff) as u8,
-			(id >> 2 & 0xff) as u8,
-			(id >> 3 & 0xff) as u8,
-		]
-	}
-	fn build_command(&mut self, command: u8, mut body: Vec<u8>) -> Vec<u8> {
-		let l = body.len();
-		let mut buffer = Vec::with_capacity(12 + l);
-		buffer.push(0x02); // STX
-		buffer.push(0x02); // version
-				   // 2-5 body length
-		buffer.push(l as u8); // 0x00; // :HACK:
-		buffer.push(0x00);
-		buffer.push(0x00);
-		buffer.push(0x00);
-		// 6-9 request id -> little endian!
-		let rid = self.generate_request_id();
-		buffer.push(rid[0]);
-		buffer.push(rid[1]);
-		buffer.push(rid[2]);
-		buffer.push(rid[3]);
-		// 10 command id
-		buffer.push(command);
-		// 11 command body
-		buffer.append(&mut body);
-
-		buffer
+		self.next_request_id = self.next_request_id.wrapping_add(1);
+		if self.next_request_id == EVENT_REQUEST_ID {
+			self.next_request_id = 0;
+		}
+		id
 	}
 
 	fn send_buffer(&mut self, buffer: &[u8]) -> anyhow::Result<()> {
-		if let Some(request_rb_prod) = &mut self.request_rb_prod {
-			for b in buffer.iter() {
-				match request_rb_prod.push(*b) {
-					Ok(()) => {},
-					Err(e) => {
-						anyhow::bail!("Error storing request {}", e);
-					},
-				}
-			}
-			Ok(())
+		if let Some(stream) = &mut self.stream {
+			packet_log::log_sent(buffer);
+			// write_all keeps going after partial writes
+			stream
+				.write_all(buffer)
+				.map_err(|e| anyhow::anyhow!("Error writing to {}: {}", self.socket_addr, e))
 		} else {
-			anyhow::bail!("No buffer when trying to send");
+			anyhow::bail!("No connection when trying to send");
 		}
 	}
 
-	fn handle_response(&mut self) -> anyhow::Result<()> {
-		if let Some(response_buffer_cons) = &mut self.response_rb_cons {
-			// occupied_len
-			if response_buffer_cons.len() >= 12 {
-				let mut header_buffer = [0u8; 12];
-				let l = response_buffer_cons.pop_slice(&mut header_buffer);
-				println!("Got {} bytes from ringbuffer for header", l);
-				if l != 12 {
-					anyhow::bail!("Short header {} != 12", l);
+	/// Sends `request` and returns its request id.
+	pub fn send_request(&mut self, request: &Request) -> anyhow::Result<u32> {
+		let request_id = self.generate_request_id();
+		let buf = request.encode(request_id)?;
+		self.send_buffer(&buf)?;
+		self.pending_requests.insert(request_id, request.command());
+
+		match request {
+			Request::MemoryGet { start, .. } => {
+				self.memory_gets_pending.push_back(*start);
+			},
+			Request::CheckpointDelete { number } => {
+				self.checkpoint_deletes_pending.push_back(*number);
+			},
+			Request::CheckpointList => {
+				self.checkpoint_lists_pending += 1;
+			},
+			Request::CheckpointToggle { number, enabled } => {
+				self.checkpoint_toggles_pending
+					.push_back((*number, *enabled));
+			},
+			Request::ConditionSet {
+				checkpoint,
+				condition,
+			} => {
+				self.condition_sets_pending
+					.push_back((*checkpoint, condition.clone()));
+			},
+			Request::RegistersAvailable { memspace }
+				if !self.registers_available_memspaces.contains(memspace) =>
+			{
+				self.registers_available_memspaces.push(*memspace);
+			},
+			Request::Reset { .. } => {
+				self.resets_pending += 1;
+			},
+			_ => {},
+		}
+		Ok(request_id)
+	}
+
+	fn handle_framed(&mut self, framed: Framed) -> anyhow::Result<()> {
+		match framed {
+			Framed::Dropped(dropped) => {
+				self.dropped_bytes += dropped;
+				log::warn!(
+					"Invalid response header, dropped {} bytes to resync ({} total)",
+					dropped,
+					self.dropped_bytes
+				);
+				Ok(())
+			},
+			Framed::Response { header, response } => self.handle_response(header, response),
+		}
+	}
+
+	fn handle_response(
+		&mut self,
+		rh: ResponseHeader,
+		decoded: Result<Response, DecodeError>,
+	) -> anyhow::Result<()> {
+		log::debug!(
+			"Got {} byte response (Response Type: {:#04x}, Error Code: {:#04x})",
+			rh.body_len(),
+			rh.response_type(),
+			rh.error_code()
+		);
+		match &decoded {
+			Err(DecodeError::UnknownType(t)) => {
+				log::warn!(
+					"Skipped {} byte body of unknown response type {:#04x}",
+					rh.body_len(),
+					t
+				);
+			},
+			Err(e) => {
+				log::warn!(
+					"Failed to decode response type {:#04x}: {}",
+					rh.response_type(),
+					e
+				);
+			},
+			Ok(_) => {},
+		}
+		let request_id = rh.request_id();
+		let condition = self.checkpoint_conditions_pending.remove(&request_id);
+		if request_id == EVENT_REQUEST_ID {
+			if let Ok(r) = &decoded {
+				self.push_event(Event::Response(r.clone()));
+			}
+		} else if let Some(&command) = self.pending_requests.get(&request_id) {
+			if protocol::completes_request(command, rh.response_type()) {
+				self.pending_requests.remove(&request_id);
+				if self.waiting_requests.remove(&request_id) {
+					self.completed_requests
+						.insert(request_id, (rh.error_code(), decoded.clone()));
+				} else if let Some(code) = ErrorCode::from_code(rh.error_code()) {
+					if self.request_errors.len() >= MAX_QUEUED_EVENTS {
+						self.request_errors.pop_front();
+					}
+					self.request_errors
+						.push_back(ViceError::new(request_id, command, code));
+				}
+			}
+		}
+
+		let r = decoded.unwrap_or(Response::Invalid);
+
+		match r {
+			Response::MemoryGet { bytes } => {
+				self.memory_start = self.memory_gets_pending.pop_front().unwrap_or(0);
+				self.memory = bytes;
+			},
+			Response::MemorySet => {},
+			Response::CheckpointInfo { checkpoint } => {
+				let number = checkpoint.number();
+				if checkpoint.hit() {
+					self.checkpoint_hit = Some(number);
 				}
-				let rh: ResponseHeader = (&header_buffer).into();
-				if !rh.valid() {
-					anyhow::bail!("Invalid response header!");
+				if self.checkpoint_lists_pending > 0 {
+					self.checkpoints_listed.push(number);
 				}
-				let body_len = rh.body_len() as usize;
-				let mut body_vec = Vec::with_capacity(body_len);
-				body_vec.resize(body_len, 0);
-				let mut body_buffer = &mut body_vec[0..body_len]; //body_vec.as_slice();
-				while body_len > response_buffer_cons.len() {
-					print!(".");
-					let short_delay = std::time::Duration::from_millis(1);
-					std::thread::sleep(short_delay);
+				self.checkpoints.insert(number, checkpoint);
+				if let Some(condition) = condition {
+					self.send_request(&Request::ConditionSet {
+						checkpoint: number,
+						condition,
+					})?;
 				}
-				let l = response_buffer_cons.pop_slice(&mut body_buffer);
-				println!("Got {} bytes from ringbuffer for body (Response Type: {:#04x}, Error Code: {:#04x})", l, rh.response_type(), rh.error_code());
-				if l != body_len {
-					anyhow::bail!("Short body {} != {}", l, body_len);
+			},
+			Response::CheckpointDelete => {
+				if let Some(number) = self.checkpoint_deletes_pending.pop_front() {
+					self.checkpoints.remove(&number);
+					self.checkpoint_conditions.remove(&number);
 				}
-
-				let r: Response = (&rh, &body_buffer[..]).into();
-
-				match r {
-					Response::RegistersGet { registers } => {
-						for (k, v) in registers {
-							let id = k;
-							let size = v.0;
-							let value = v.1;
-							let r = self
-								.registers
-								.entry(id)
-								.or_insert_with(|| Register::default());
-							println!("{:#04x} {:#04x} {:#06x} | {}", size, id, value, r.name());
-							r.set_value(value);
-						}
-					},
-					Response::Stopped { pc } => {
-						self.running = false;
-						self.program_counter = pc;
-						//println!("stopped PC {:#06x}", pc);
-					},
-					Response::Resumed { pc } => {
-						self.running = true;
-						self.program_counter = pc;
-						//println!("resumed PC {:#06x}", pc);
-					},
-					/*
-					0x71 => { // advance instructions
-					},
-					0x81 => { // ping
-					},
-					*/
-					Response::RegistersAvailable { registers } => {
-						for (k, v) in registers {
-							let id = k;
-							let r_size = v.0;
-							let name = v.1;
-
-							println!("{:#04x} {:#04x} -> {}", id, r_size, name);
-							let r = self
-								.registers
-								.entry(id)
-								.or_insert_with(|| Register::default());
-							r.set_name(&name);
-							r.set_size(r_size);
+			},
+			Response::CheckpointList { count } => {
+				log::debug!("Checkpoint list with {} entries", count);
+				let listed = std::mem::take(&mut self.checkpoints_listed);
+				self.checkpoints.retain(|n, _| listed.contains(n));
+				self.checkpoint_conditions.retain(|n, _| listed.contains(n));
+				self.checkpoint_lists_pending = self.checkpoint_lists_pending.saturating_sub(1);
+			},
+			Response::ConditionSet => {
+				if let Some((number, condition)) = self.condition_sets_pending.pop_front() {
+					if let Some(checkpoint) = self.checkpoints.get_mut(&number) {
+						checkpoint.set_has_condition(true);
+					}
+					self.checkpoint_conditions.insert(number, condition);
+				}
+			},
+			Response::CheckpointToggle => {
+				if let Some((number, enabled)) = self.checkpoint_toggles_pending.pop_front() {
+					if let Some(checkpoint) = self.checkpoints.get_mut(&number) {
+						checkpoint.set_enabled(enabled);
+					}
+				}
+			},
+			Response::RegistersGet { registers } => {
+				for (id, value) in registers {
+					let r = self.registers.entry(id).or_default();
+					log::trace!("{:#04x} {:#06x} | {}", id, value, r.name());
+					r.set_value(value);
+				}
+			},
+			Response::Stopped { pc } => {
+				self.running = false;
+				self.program_counter = pc;
+				self.stop_count += 1;
+				self.stopped_by_checkpoint = self.checkpoint_hit.take();
+				if let Some(number) = self.stopped_by_checkpoint {
+					log::info!("Stopped at {:#06x} by checkpoint {}", pc, number);
+					if let Some(checkpoint) = self.checkpoints.get(&number) {
+						for callback in self.checkpoint_hit_callbacks.iter_mut() {
+							callback(checkpoint, pc);
 						}
-					},
-					Response::Exit => {},
-					Response::Reset => {
-						// reset
-						println!("Handled reset");
-						self.resets_pending -= 1;
-					},
-					_o => match rh.error_code() {
-						0x80 => {
-							println!("Invalid command length for {:#010x}", rh.request_id());
-						},
-						ec => {
-							println!(
-								"Unhandled response type {:#04x} (error code: {:#04x})",
-								rh.response_type(),
-								ec
-							);
-						},
-					},
+					}
+					// VICE removes temporary checkpoints once they are hit
+					if self.checkpoints.get(&number).is_some_and(|c| c.temporary()) {
+						self.checkpoints.remove(&number);
+					}
 				}
-			}
+				for callback in self.stopped_callbacks.iter_mut() {
+					callback(pc);
+				}
+				//println!("stopped PC {:#06x}", pc);
+			},
+			Response::Resumed { pc } => {
+				self.running = true;
+				self.program_counter = pc;
+				for callback in self.resumed_callbacks.iter_mut() {
+					callback(pc);
+				}
+				//println!("resumed PC {:#06x}", pc);
+			},
+			Response::Jam { pc } => {
+				log::warn!("CPU jammed at {:#06x}", pc);
+				self.running = false;
+				self.program_counter = pc;
+				self.stop_count += 1;
+				for callback in self.jam_callbacks.iter_mut() {
+					callback(pc);
+				}
+			},
+			/*
+			0x71 => { // advance instructions
+			},
+			0x81 => { // ping
+			},
+			*/
+			Response::RegistersAvailable { registers } => {
+				for (k, v) in registers {
+					let id = k;
+					let r_size = v.0;
+					let name = v.1;
+
+					log::trace!("{:#04x} {:#04x} -> {}", id, r_size, name);
+					let r = self.registers.entry(id).or_default();
+					r.set_name(&name);
+					r.set_size(r_size);
+				}
+			},
+			Response::Undump { pc } => {
+				log::debug!("Undump, PC {:#06x}", pc);
+				self.program_counter = pc;
+			},
+			Response::Exit => {},
+			Response::Reset => {
+				// reset
+				log::debug!("Handled reset");
+				self.resets_pending -= 1;
+			},
+			_o => match ErrorCode::from_code(rh.error_code()) {
+				Some(ec) if rh.response_type() == 0x01 => {
+					self.memory_gets_pending.pop_front();
+					log::warn!("Memory get failed with {}", ec);
+				},
+				Some(ec) if rh.response_type() == 0x13 => {
+					self.checkpoint_deletes_pending.pop_front();
+					log::warn!("Checkpoint delete failed with {}", ec);
+				},
+				Some(ec) if rh.response_type() == 0x14 => {
+					self.checkpoints_listed.clear();
+					self.checkpoint_lists_pending = self.checkpoint_lists_pending.saturating_sub(1);
+					log::warn!("Checkpoint list failed with {}", ec);
+				},
+				Some(ec) if rh.response_type() == 0x22 => {
+					self.condition_sets_pending.pop_front();
+					log::warn!("Condition set failed with {}", ec);
+				},
+				Some(ec) if rh.response_type() == 0x15 => {
+					self.checkpoint_toggles_pending.pop_front();
+					log::warn!("Checkpoint toggle failed with {}", ec);
+				},
+				Some(ec) => {
+					log::warn!(
+						"Request {:#010x} (response type {:#04x}) failed with {}",
+						rh.request_id(),
+						rh.response_type(),
+						ec
+					);
+				},
+				None => {
+					log::debug!("Unhandled response type {:#04x}", rh.response_type());
+				},
+			},
 		}
 
 		Ok(())
 	}
 
 	pub fn update(&mut self) -> anyhow::Result<()> {
-		println!("{} {:?}", &self.resets_pending, self.load_pending);
+		log::trace!(
+			"Resets pending: {}, load pending: {}",
+			self.resets_pending,
+			self.load_pending
+		);
+		self.handle_responses()
+	}
+
+	/// Blocks until something arrives from the I/O thread, or `deadline` passes.
+	fn wait_for_response(&mut self, deadline: Instant) -> anyhow::Result<()> {
+		let wait = deadline
+			.saturating_duration_since(Instant::now())
+			.min(MAX_RESPONSE_WAIT);
+		let received = match &self.response_rx {
+			Some(response_rx) => response_rx.recv_timeout(wait),
+			None => Err(RecvTimeoutError::Disconnected),
+		};
+		match received {
+			Ok(framed) => self.handle_framed(framed),
+			Err(RecvTimeoutError::Timeout) => Ok(()),
+			// connection loss is reported by the next handle_responses
+			Err(RecvTimeoutError::Disconnected) => {
+				thread::sleep(wait);
+				Ok(())
+			},
+		}
+	}
+
+	fn handle_responses(&mut self) -> anyhow::Result<()> {
 		if self.connected {
-			loop {
-				let l = if let Some(response_buffer_cons) = &mut self.response_rb_cons {
-					response_buffer_cons.len()
-				} else {
-					0
-				};
-				if l >= 12 {
-					self.handle_response()?;
-				} else {
-					break;
+			// checked before draining, so nothing sent right before the thread stopped is lost
+			let lost = !self.is_connected();
+			while let Some(framed) = self
+				.response_rx
+				.as_ref()
+				.and_then(|response_rx| response_rx.try_recv().ok())
+			{
+				self.handle_framed(framed)?;
+			}
+			if lost {
+				// surfaces the error the I/O thread stopped with
+				let lost = self.disconnect();
+				if self.reconnect_policy.is_some() {
+					if let Err(e) = lost {
+						log::warn!("Connection to {} lost: {}", self.socket_addr, e);
+					}
+					return self.reconnect();
 				}
+				lost?;
+				anyhow::bail!("Connection to {} lost", self.socket_addr);
 			}
 
 			Ok(())
@@ -384,27 +783,220 @@ impl FakeViceBin {
 		}
 	}
 
+	/// Connects again following the reconnect policy, then re-sends the recorded session state.
+	fn reconnect(&mut self) -> anyhow::Result<()> {
+		let policy = self.reconnect_policy.clone().unwrap_or_default();
+		for attempt in 1..=policy.max_attempts() {
+			thread::sleep(policy.delay(attempt));
+			log::info!(
+				"Reconnecting to {} (attempt {}/{})",
+				self.socket_addr,
+				attempt,
+				policy.max_attempts()
+			);
+			match self.connect() {
+				Ok(()) => {
+					self.replay_session()?;
+					self.reconnect_count += 1;
+					self.push_event(Event::Reconnected { attempts: attempt });
+					return Ok(());
+				},
+				Err(e) => {
+					log::warn!("Reconnect failed: {}", e);
+				},
+			}
+		}
+		anyhow::bail!(
+			"Connection to {} lost, gave up after {} attempt(s)",
+			self.socket_addr,
+			policy.max_attempts()
+		);
+	}
+
+	/// VICE might have been restarted, so checkpoints are set again and get new numbers.
+	fn replay_session(&mut self) -> anyhow::Result<()> {
+		for memspace in self.registers_available_memspaces.clone() {
+			self.send_request(&Request::RegistersAvailable { memspace })?;
+		}
+
+		let mut checkpoints = std::mem::take(&mut self.checkpoints)
+			.into_values()
+			.collect::<Vec<_>>();
+		checkpoints.sort_by_key(|c| c.number());
+		let mut conditions = std::mem::take(&mut self.checkpoint_conditions);
+		for checkpoint in checkpoints {
+			let request_id = self.send_request(&Request::CheckpointSet {
+				start:         checkpoint.start(),
+				end:           checkpoint.end(),
+				stop_when_hit: checkpoint.stop_when_hit(),
+				enabled:       checkpoint.enabled(),
+				operation:     checkpoint.operation(),
+				temporary:     checkpoint.temporary(),
+			})?;
+			if let Some(condition) = conditions.remove(&checkpoint.number()) {
+				self.checkpoint_conditions_pending
+					.insert(request_id, condition);
+			}
+		}
+		Ok(())
+	}
+
+	/// Sends `request` and blocks until its response arrives, or `timeout` passes.
+	pub fn send_and_wait(
+		&mut self,
+		request: &Request,
+		timeout: Duration,
+	) -> anyhow::Result<Response> {
+		if self.connected {
+			let request_id = self.send_request(request)?;
+			self.waiting_requests.insert(request_id);
+
+			let deadline = Instant::now() + timeout;
+			loop {
+				// the answer might have arrived right before the connection went away
+				let handled = self.handle_responses();
+				if let Some((error_code, response)) = self.completed_requests.remove(&request_id) {
+					if let Some(code) = ErrorCode::from_code(error_code) {
+						return Err(ViceError::new(request_id, request.command(), code).into());
+					}
+					return Ok(response?);
+				}
+				if let Err(e) = handled {
+					self.waiting_requests.remove(&request_id);
+					return Err(e);
+				}
+				if !self.pending_requests.contains_key(&request_id) {
+					// dropped by a reconnect
+					anyhow::bail!(
+						"Connection lost before response to request {:#010x} (command {:#04x})",
+						request_id,
+						request.command()
+					);
+				}
+				if Instant::now() >= deadline {
+					self.waiting_requests.remove(&request_id);
+					anyhow::bail!(
+						"Timeout waiting for response to request {:#010x} (command {:#04x})",
+						request_id,
+						request.command()
+					);
+				}
+				if let Err(e) = self.wait_for_response(deadline) {
+					self.waiting_requests.remove(&request_id);
+					return Err(e);
+				}
+			}
+		} else {
+			anyhow::bail!("Not connected to send command {:#04x}", request.command());
+		}
+	}
+
+	/// Blocks until every request sent so far has been answered, or `timeout` passes.
+	///
+	/// Fails with the first `ViceError` recorded since the last wait, later ones are only logged.
+	pub fn wait_for_pending(&mut self, timeout: Duration) -> anyhow::Result<()> {
+		let deadline = Instant::now() + timeout;
+		loop {
+			self.handle_responses()?;
+			if self.pending_requests.is_empty() {
+				let mut errors = std::mem::take(&mut self.request_errors).into_iter();
+				return match errors.next() {
+					Some(e) => {
+						for other in errors {
+							log::warn!("{}", other);
+						}
+						Err(e.into())
+					},
+					None => Ok(()),
+				};
+			}
+			if Instant::now() >= deadline {
+				anyhow::bail!(
+					"Timeout waiting for {} pending request(s)",
+					self.pending_requests.len()
+				);
+			}
+			self.wait_for_response(deadline)?;
+		}
+	}
+
+	/// Blocks until VICE reports the CPU stopped or jammed, returns the program counter.
+	pub fn wait_until_stopped(&mut self, timeout: Duration) -> anyhow::Result<u16> {
+		let deadline = Instant::now() + timeout;
+		loop {
+			self.handle_responses()?;
+			if !self.running {
+				return Ok(self.program_counter);
+			}
+			if Instant::now() >= deadline {
+				anyhow::bail!("Timeout waiting for CPU to stop");
+			}
+			self.wait_for_response(deadline)?;
+		}
+	}
+
+	/// Grabs the visible screen area, with the colors of the current palette.
+	pub fn screenshot(&mut self, timeout: Duration) -> anyhow::Result<Screenshot> {
+		let colors = match self.send_and_wait(&Request::PaletteGet { use_vicii: true }, timeout)? {
+			Response::Palette { colors } => colors,
+			r => anyhow::bail!("Unexpected response {:?} to palette get", r),
+		};
+		let display = self.send_and_wait(
+			&Request::DisplayGet {
+				use_vicii: true,
+				format:    0, // indexed, 8 bit
+			},
+			timeout,
+		)?;
+		Screenshot::from_display(&display, &colors)
+	}
+
+	/// Reads memory as seen by the CPU, without side effects.
+	pub fn read_memory(
+		&mut self,
+		start: u16,
+		end: u16,
+		timeout: Duration,
+	) -> anyhow::Result<Vec<u8>> {
+		if end < start {
+			anyhow::bail!("Invalid memory range {:#06x} - {:#06x}", start, end);
+		}
+		let request = Request::MemoryGet {
+			side_effects: false,
+			start,
+			end,
+			memspace: 0,
+			bank: 0,
+		};
+		match self.send_and_wait(&request, timeout)? {
+			Response::MemoryGet { bytes } => Ok(bytes),
+			r => anyhow::bail!("Unexpected response {:?} to memory get", r),
+		}
+	}
+
+	/// Reads the 40x25 text screen, one line per row.
+	///
+	/// Screen RAM is at $0400 in upper case, with `follow_vic` the VIC bank ($dd00),
+	/// and screen location and character set ($d018) are used instead.
+	pub fn screen_text(&mut self, follow_vic: bool, timeout: Duration) -> anyhow::Result<String> {
+		let (start, lower_case) = if follow_vic {
+			let cia2 = self.read_memory(0xdd00, 0xdd00, timeout)?;
+			let d018 = self.read_memory(0xd018, 0xd018, timeout)?;
+			let (cia2, d018) = (cia2[0], d018[0]);
+			let bank = (3 - (cia2 & 0x03) as u16) * 0x4000;
+			(bank + (d018 >> 4) as u16 * 0x0400, d018 & 0x02 != 0)
+		} else {
+			(0x0400, false)
+		};
+		let len = (screen_text::SCREEN_COLUMNS * screen_text::SCREEN_ROWS) as u16;
+		let codes = self.read_memory(start, start + len - 1, timeout)?;
+		Ok(screen_text::screen_to_text(&codes, lower_case))
+	}
+
 	pub fn send_ping(&mut self) -> anyhow::Result<()> {
 		if self.connected {
-			let mut buf = Vec::new();
-
-			buf.push(0x02); // STX
-			buf.push(0x02); // version
-				// 2-5 body length
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			// 6-9 request id -> little endian!
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			// 10 command id
-			buf.push(0x81); // 0x81 -> ping
-				// 11 command body
-
-			self.send_buffer(&buf)
+			self.send_request(&Request::Ping)?;
+			Ok(())
 		} else {
 			anyhow::bail!("Not connected to send ping");
 		}
@@ -412,25 +1004,8 @@ impl FakeViceBin {
 
 	pub fn send_exit(&mut self) -> anyhow::Result<()> {
 		if self.connected {
-			let mut buf = Vec::new();
-
-			buf.push(0x02); // STX
-			buf.push(0x02); // version
-				// 2-5 body length
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			// 6-9 request id -> little endian!
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			buf.push(0x00);
-			// 10 command id
-			buf.push(0xaa); // 0xaa -> exit
-				// 11 command body
-
-			self.send_buffer(&buf)
+			self.send_request(&Request::Exit)?;
+			Ok(())
 		} else {
 			anyhow::bail!("Not connected to send exit");
 		}
@@ -438,66 +1013,650 @@ impl FakeViceBin {
 
 	pub fn send_reset(&mut self) -> anyhow::Result<()> {
 		if self.connected {
-			let mut body = Vec::new();
-			body.push(0x01); // 0x01 -> hard reset
-
-			let buf = self.build_command(0xcc, body);
-			self.resets_pending += 1;
-			self.send_buffer(&buf)
+			self.send_request(&Request::Reset { kind: 0x01 })?; // 0x01 -> hard reset
+			Ok(())
 		} else {
 			anyhow::bail!("Not connected to send reset");
 		}
 	}
 	pub fn send_load(&mut self, filename: &str, autostart: bool) -> anyhow::Result<()> {
 		if self.connected {
-			let filename_bytes: &[u8] = filename.as_bytes();
 			// :TODO: ascii cleanup/check
-			let filename_len = filename_bytes.len();
-			// :TODO: check length
-
-			let mut body = Vec::new();
-			if autostart {
-				body.push(0x01); // autostart
-			} else {
-				body.push(0x00); // no autostart
-			}
-			// file index of disk image
-			body.push(0x00);
-			body.push(0x00);
-			body.push(filename_len as u8);
-			for b in filename_bytes.iter() {
-				body.push(*b);
+			if filename.len() > 0xff {
+				anyhow::bail!("Filename too long ({} bytes): {}", filename.len(), filename);
 			}
-
-			let buf = self.build_command(0xdd, body);
-			self.send_buffer(&buf)
+			self.send_request(&Request::AutoStart {
+				run:        autostart,
+				file_index: 0, // file index of disk image
+				filename:   filename.to_owned(),
+			})?;
+			Ok(())
 		} else {
 			anyhow::bail!("Not connected to send load");
 		}
 	}
+	/// Saves a snapshot (.vsf) of the machine, `filename` is on the VICE side.
+	pub fn send_dump(
+		&mut self,
+		filename: &str,
+		save_roms: bool,
+		save_disks: bool,
+	) -> anyhow::Result<()> {
+		if self.connected {
+			if filename.len() > 0xff {
+				anyhow::bail!("Filename too long ({} bytes): {}", filename.len(), filename);
+			}
+			self.send_request(&Request::Dump {
+				save_roms,
+				save_disks,
+				filename: filename.to_owned(),
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send dump");
+		}
+	}
+	/// Loads a snapshot, `pc()` follows once VICE answered.
+	pub fn send_undump(&mut self, filename: &str) -> anyhow::Result<()> {
+		if self.connected {
+			if filename.len() > 0xff {
+				anyhow::bail!("Filename too long ({} bytes): {}", filename.len(), filename);
+			}
+			self.send_request(&Request::Undump {
+				filename: filename.to_owned(),
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send undump");
+		}
+	}
 	pub fn send_registers_available(&mut self, memspace: u8) -> anyhow::Result<()> {
-		println!("send_registers_available");
+		log::debug!("send_registers_available");
 		if self.connected {
-			let mut body = Vec::new();
-			body.push(memspace);
-
-			let buf = self.build_command(0x83, body);
-			self.send_buffer(&buf)
+			self.send_request(&Request::RegistersAvailable { memspace })?;
+			Ok(())
 		} else {
 			anyhow::bail!("Not connected to send registers available");
 		}
 	}
-	pub fn send_advance_instructions(&mut self, count: u16) -> anyhow::Result<()> {
+	pub fn send_memory_get(
+		&mut self,
+		start: u16,
+		end: u16,
+		memspace: u8,
+		bank: u16,
+		side_effects: bool,
+	) -> anyhow::Result<()> {
 		if self.connected {
-			let mut body = Vec::new();
-			body.push(0); // do not step over subroutines
-			body.push(((count >> 0) & 0xff) as u8);
-			body.push(((count >> 1) & 0xff) as u8);
-
-			let buf = self.build_command(0x71, body);
-			self.send_buffer(&buf)
+			if end < start {
+				anyhow::bail!("Invalid memory range {:#06x} - {:#06x}", start, end);
+			}
+			self.send_request(&Request::MemoryGet {
+				side_effects,
+				start,
+				end,
+				memspace,
+				bank,
+			})?;
+			Ok(())
 		} else {
-			anyhow::bail!("Not connected to send registers available");
+			anyhow::bail!("Not connected to send memory get");
+		}
+	}
+	pub fn send_memory_set(
+		&mut self,
+		start: u16,
+		bytes: &[u8],
+		memspace: u8,
+		bank: u16,
+		side_effects: bool,
+	) -> anyhow::Result<()> {
+		if self.connected {
+			if bytes.is_empty() || start as usize + bytes.len() > 0x10000 {
+				anyhow::bail!(
+					"Invalid memory range {:#06x} + {} bytes",
+					start,
+					bytes.len()
+				);
+			}
+			self.send_request(&Request::MemorySet {
+				side_effects,
+				start,
+				memspace,
+				bank,
+				bytes: bytes.to_vec(),
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send memory set");
+		}
+	}
+	pub fn send_checkpoint_get(&mut self, number: u32) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::CheckpointGet { number })?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send checkpoint get");
+		}
+	}
+	pub fn send_checkpoint_set(
+		&mut self,
+		start: u16,
+		end: u16,
+		stop_when_hit: bool,
+		enabled: bool,
+		operation: CheckpointOperation,
+		temporary: bool,
+	) -> anyhow::Result<()> {
+		if self.connected {
+			if end < start {
+				anyhow::bail!("Invalid checkpoint range {:#06x} - {:#06x}", start, end);
+			}
+			self.send_request(&Request::CheckpointSet {
+				start,
+				end,
+				stop_when_hit,
+				enabled,
+				operation,
+				temporary,
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send checkpoint set");
+		}
+	}
+	/// Sets a stopping checkpoint and attaches `condition` once VICE reports its number.
+	pub fn send_checkpoint_set_with_condition(
+		&mut self,
+		start: u16,
+		end: u16,
+		operation: CheckpointOperation,
+		condition: &ConditionExpr,
+	) -> anyhow::Result<()> {
+		if self.connected {
+			if end < start {
+				anyhow::bail!("Invalid checkpoint range {:#06x} - {:#06x}", start, end);
+			}
+			let condition = condition.to_string();
+			if condition.len() > 0xff {
+				anyhow::bail!(
+					"Condition too long ({} bytes): {}",
+					condition.len(),
+					condition
+				);
+			}
+			let request_id = self.send_request(&Request::CheckpointSet {
+				start,
+				end,
+				stop_when_hit: true,
+				enabled: true,
+				operation,
+				temporary: false,
+			})?;
+			self.checkpoint_conditions_pending
+				.insert(request_id, condition);
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send checkpoint set");
+		}
+	}
+	pub fn send_condition_set(
+		&mut self,
+		checkpoint: u32,
+		condition: &ConditionExpr,
+	) -> anyhow::Result<()> {
+		if self.connected {
+			let condition = condition.to_string();
+			if condition.len() > 0xff {
+				anyhow::bail!(
+					"Condition too long ({} bytes): {}",
+					condition.len(),
+					condition
+				);
+			}
+			self.send_request(&Request::ConditionSet {
+				checkpoint,
+				condition,
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send condition set");
+		}
+	}
+	pub fn send_checkpoint_delete(&mut self, number: u32) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::CheckpointDelete { number })?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send checkpoint delete");
+		}
+	}
+	pub fn send_checkpoint_list(&mut self) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::CheckpointList)?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send checkpoint list");
+		}
+	}
+	pub fn send_checkpoint_toggle(&mut self, number: u32, enabled: bool) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::CheckpointToggle { number, enabled })?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send checkpoint toggle");
+		}
+	}
+	pub fn set_register(&mut self, name: &str, value: u16) -> anyhow::Result<()> {
+		self.set_registers(&[(name, value)])
+	}
+	/// Writes all given registers with a single registers set request.
+	pub fn set_registers(&mut self, values: &[(&str, u16)]) -> anyhow::Result<()> {
+		let mut ids = Vec::with_capacity(values.len());
+		for (name, value) in values.iter() {
+			match self.register_id(name) {
+				Some(id) => ids.push((id, *value)),
+				None => anyhow::bail!(
+					"Unknown register {} (registers available: {})",
+					name,
+					self.registers.len()
+				),
+			}
+		}
+		self.send_registers_set(0, &ids)
+	}
+	pub fn send_registers_set(&mut self, memspace: u8, values: &[(u8, u16)]) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::RegistersSet {
+				memspace,
+				registers: values.to_vec(),
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send registers set");
+		}
+	}
+	pub fn send_advance_instructions(
+		&mut self,
+		count: u16,
+		step_over_subroutines: bool,
+	) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::AdvanceInstructions {
+				step_over_subroutines,
+				count,
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send advance instructions");
+		}
+	}
+	pub fn send_execute_until_return(&mut self) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::ExecuteUntilReturn)?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send execute until return");
+		}
+	}
+	/// Executes `count` instructions, into subroutines, returns the program counter afterwards.
+	pub fn step(&mut self, count: u16, timeout: Duration) -> anyhow::Result<u16> {
+		self.run_until_stopped(
+			&Request::AdvanceInstructions {
+				step_over_subroutines: false,
+				count,
+			},
+			timeout,
+		)
+	}
+	/// Like `step`, but a subroutine call counts as one instruction.
+	pub fn next(&mut self, count: u16, timeout: Duration) -> anyhow::Result<u16> {
+		self.run_until_stopped(
+			&Request::AdvanceInstructions {
+				step_over_subroutines: true,
+				count,
+			},
+			timeout,
+		)
+	}
+	/// Runs until the current subroutine returns, returns the program counter afterwards.
+	pub fn finish(&mut self, timeout: Duration) -> anyhow::Result<u16> {
+		self.run_until_stopped(&Request::ExecuteUntilReturn, timeout)
+	}
+	// VICE answers first and reports the stop later, it might come in the same read as the answer
+	fn run_until_stopped(&mut self, request: &Request, timeout: Duration) -> anyhow::Result<u16> {
+		let deadline = Instant::now() + timeout;
+		let stop_count = self.stop_count;
+		self.send_and_wait(request, timeout)?;
+		loop {
+			self.handle_responses()?;
+			if self.stop_count != stop_count {
+				return Ok(self.program_counter);
+			}
+			if Instant::now() >= deadline {
+				anyhow::bail!("Timeout waiting for CPU to stop");
+			}
+			self.wait_for_response(deadline)?;
+		}
+	}
+	/// Types `text` via the keyboard buffer, see `to_petscii` for the escapes.
+	pub fn send_keyboard_feed(&mut self, text: &str) -> anyhow::Result<()> {
+		if self.connected {
+			let petscii = to_petscii(text)?;
+			// the length is a single byte
+			for chunk in petscii.chunks(0xff) {
+				self.send_request(&Request::KeyboardFeed {
+					petscii: chunk.to_vec(),
+				})?;
+			}
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send keyboard feed");
+		}
+	}
+	/// `port` is 1 or 2, as labeled on the C64.
+	pub fn send_joystick(&mut self, port: u8, state: JoystickState) -> anyhow::Result<()> {
+		if !(1..=2).contains(&port) {
+			anyhow::bail!("Invalid joystick port {}, expected 1 or 2", port);
+		}
+		if self.connected {
+			self.send_request(&Request::JoyportSet {
+				port:  port as u16 - 1, // VICE counts from 0
+				value: state.bits() as u16,
+			})?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send joystick");
 		}
 	}
+	pub fn send_userport(&mut self, value: u16) -> anyhow::Result<()> {
+		if self.connected {
+			self.send_request(&Request::UserportSet { value })?;
+			Ok(())
+		} else {
+			anyhow::bail!("Not connected to send userport");
+		}
+	}
+}
+
+impl Drop for FakeViceBin {
+	fn drop(&mut self) {
+		if let Err(e) = self.disconnect() {
+			log::warn!("Error while disconnecting: {}", e);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::net::TcpListener;
+
+	use super::*;
+
+	const TIMEOUT: Duration = Duration::from_secs(5);
+
+	fn read_request(stream: &mut TcpStream) -> Option<(u32, Request)> {
+		let mut header = [0u8; 11];
+		stream.read_exact(&mut header).ok()?;
+		let body_len = u32::from_le_bytes([header[2], header[3], header[4], header[5]]);
+		let request_id = u32::from_le_bytes([header[6], header[7], header[8], header[9]]);
+		let mut body = vec![0; body_len as usize];
+		stream.read_exact(&mut body).ok()?;
+		Some((request_id, Request::try_from((header[10], &body[..])).ok()?))
+	}
+
+	// numbers checkpoints from `first_checkpoint`, returns after answering a condition set
+	fn serve_checkpoints(
+		stream: &mut TcpStream,
+		first_checkpoint: u32,
+		requests: &mpsc::Sender<Request>,
+	) -> anyhow::Result<()> {
+		let mut number = first_checkpoint;
+		while let Some((request_id, request)) = read_request(stream) {
+			let response = match &request {
+				Request::CheckpointSet {
+					start,
+					end,
+					stop_when_hit,
+					enabled,
+					operation,
+					temporary,
+				} => {
+					number += 1;
+					Response::CheckpointInfo {
+						checkpoint: Checkpoint::new(
+							number - 1,
+							false,
+							*start,
+							*end,
+							*stop_when_hit,
+							*enabled,
+							*operation,
+							*temporary,
+							0,
+							0,
+							false,
+							0,
+						),
+					}
+				},
+				Request::ConditionSet { .. } => Response::ConditionSet,
+				_ => Response::Ping,
+			};
+			stream.write_all(&response.encode(request_id))?;
+			let done = matches!(request, Request::ConditionSet { .. });
+			let _ = requests.send(request);
+			if done {
+				break;
+			}
+		}
+		Ok(())
+	}
+
+	fn wait_for_event(fvb: &mut FakeViceBin) -> anyhow::Result<Event> {
+		let deadline = Instant::now() + TIMEOUT;
+		loop {
+			fvb.update()?;
+			if let Some(event) = fvb.pop_event() {
+				return Ok(event);
+			}
+			if Instant::now() >= deadline {
+				anyhow::bail!("Timeout waiting for event");
+			}
+			thread::sleep(Duration::from_millis(1));
+		}
+	}
+
+	#[test]
+	fn reconnect_replays_session() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let (requests_tx, requests_rx) = mpsc::channel();
+		let server = thread::spawn(move || -> anyhow::Result<()> {
+			// the first connection goes away once the condition is set
+			let (mut stream, _) = listener.accept()?;
+			serve_checkpoints(&mut stream, 1, &mpsc::channel().0)?;
+			drop(stream);
+			let (mut stream, _) = listener.accept()?;
+			serve_checkpoints(&mut stream, 7, &requests_tx)?;
+			// keep the connection until the client goes away
+			while read_request(&mut stream).is_some() {}
+			Ok(())
+		});
+
+		let mut fvb = FakeViceBin::new("127.0.0.1", port).unwrap();
+		fvb.set_reconnect_policy(Some(ReconnectPolicy::new(
+			3,
+			Duration::from_millis(1),
+			Duration::from_millis(10),
+		)));
+		fvb.connect().unwrap();
+		let condition = "A == $10".parse::<ConditionExpr>().unwrap();
+		fvb.send_checkpoint_set_with_condition(
+			0xc000,
+			0xc010,
+			CheckpointOperation::EXEC,
+			&condition,
+		)
+		.unwrap();
+		// the connection might already be back when this returns
+		fvb.wait_for_pending(TIMEOUT).unwrap();
+
+		assert_eq!(
+			wait_for_event(&mut fvb).unwrap(),
+			Event::Reconnected { attempts: 1 }
+		);
+		assert_eq!(fvb.reconnect_count(), 1);
+		fvb.wait_for_pending(TIMEOUT).unwrap();
+
+		// the checkpoint is set again, and the condition attached to its new number
+		match requests_rx.recv_timeout(TIMEOUT).unwrap() {
+			Request::CheckpointSet { start, end, .. } => assert_eq!((start, end), (0xc000, 0xc010)),
+			r => panic!("Unexpected {:?}", r),
+		}
+		assert_eq!(
+			requests_rx.recv_timeout(TIMEOUT).unwrap(),
+			Request::ConditionSet {
+				checkpoint: 7,
+				condition:  condition.to_string(),
+			}
+		);
+		assert_eq!(fvb.checkpoints().keys().collect::<Vec<_>>(), vec![&7]);
+		assert!(fvb.checkpoints()[&7].has_condition());
+
+		fvb.disconnect().unwrap();
+		server.join().unwrap().unwrap();
+	}
+
+	#[test]
+	fn reconnect_gives_up() {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let port = listener.local_addr().unwrap().port();
+		// accepts once, then closes the connection and stops listening
+		let server = thread::spawn(move || listener.accept().map(|_| ()));
+
+		let mut fvb = FakeViceBin::new("127.0.0.1", port).unwrap();
+		fvb.set_reconnect_policy(Some(ReconnectPolicy::new(
+			2,
+			Duration::from_millis(1),
+			Duration::from_millis(10),
+		)));
+		fvb.connect().unwrap();
+		server.join().unwrap().unwrap();
+
+		let e = wait_for_event(&mut fvb).unwrap_err();
+		assert!(
+			e.to_string().contains("gave up after 2 attempt(s)"),
+			"{}",
+			e
+		);
+		assert_eq!(fvb.reconnect_count(), 0);
+		assert!(!fvb.is_connected());
+	}
+
+	// runs `responses` through the framer and the client, as the I/O thread would
+	fn feed(fvb: &mut FakeViceBin, responses: &[Response]) {
+		let mut framer = ResponseFramer::new(MAX_BODY_LEN);
+		for response in responses {
+			framer.push(&response.encode(EVENT_REQUEST_ID));
+		}
+		while let Some(framed) = framer.next() {
+			fvb.handle_framed(framed).unwrap();
+		}
+	}
+
+	#[test]
+	fn callbacks() {
+		let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+		let mut fvb = FakeViceBin::new("127.0.0.1", 6502).unwrap();
+		let c = calls.clone();
+		fvb.on_checkpoint_hit(move |checkpoint, pc| {
+			c.lock()
+				.unwrap()
+				.push(format!("hit {} {:#06x}", checkpoint.number(), pc))
+		});
+		let c = calls.clone();
+		fvb.on_stopped(move |pc| c.lock().unwrap().push(format!("stopped {:#06x}", pc)));
+		let c = calls.clone();
+		fvb.on_resumed(move |pc| c.lock().unwrap().push(format!("resumed {:#06x}", pc)));
+		let c = calls.clone();
+		fvb.on_jam(move |pc| c.lock().unwrap().push(format!("jam {:#06x}", pc)));
+
+		let checkpoint = Checkpoint::new(
+			3,
+			true,
+			0xc000,
+			0xc000,
+			true,
+			true,
+			CheckpointOperation::EXEC,
+			false,
+			1,
+			0,
+			false,
+			0,
+		);
+		feed(
+			&mut fvb,
+			&[
+				Response::Resumed { pc: 0x0801 },
+				Response::CheckpointInfo { checkpoint },
+				Response::Stopped { pc: 0xc000 },
+				Response::Stopped { pc: 0xc003 },
+				Response::Jam { pc: 0xc004 },
+			],
+		);
+		assert_eq!(
+			*calls.lock().unwrap(),
+			vec![
+				"resumed 0x0801",
+				"hit 3 0xc000",
+				"stopped 0xc000",
+				"stopped 0xc003",
+				"jam 0xc004",
+			]
+		);
+		assert_eq!(fvb.stopped_by_checkpoint(), None);
+		assert!(!fvb.is_running());
+
+		calls.lock().unwrap().clear();
+		fvb.clear_callbacks();
+		feed(
+			&mut fvb,
+			&[
+				Response::Resumed { pc: 0x0801 },
+				Response::Stopped { pc: 0xc000 },
+			],
+		);
+		assert!(calls.lock().unwrap().is_empty());
+	}
+
+	#[test]
+	fn temporary_checkpoint_is_removed_when_hit() {
+		let mut fvb = FakeViceBin::new("127.0.0.1", 6502).unwrap();
+		let checkpoint = Checkpoint::new(
+			4,
+			true,
+			0x080d,
+			0x080d,
+			true,
+			true,
+			CheckpointOperation::EXEC,
+			true,
+			1,
+			0,
+			false,
+			0,
+		);
+		feed(
+			&mut fvb,
+			&[
+				Response::CheckpointInfo { checkpoint },
+				Response::Stopped { pc: 0x080d },
+			],
+		);
+		assert_eq!(fvb.stopped_by_checkpoint(), Some(4));
+		assert_eq!(fvb.pc(), 0x080d);
+		assert!(fvb.checkpoints().is_empty());
+	}
 }
@@ -0,0 +1,83 @@
+use std::ops::BitOr;
+use std::str::FromStr;
+
+/// Directions and fire held on a joystick, can be combined with `|`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct JoystickState(u8);
+
+impl JoystickState {
+	pub const NONE: Self = Self(0x00);
+	pub const UP: Self = Self(0x01);
+	pub const DOWN: Self = Self(0x02);
+	pub const LEFT: Self = Self(0x04);
+	pub const RIGHT: Self = Self(0x08);
+	pub const FIRE: Self = Self(0x10);
+
+	pub fn from_bits(bits: u8) -> Self {
+		Self(bits & 0x1f)
+	}
+	pub fn bits(&self) -> u8 {
+		self.0
+	}
+	pub fn contains(&self, other: Self) -> bool {
+		self.0 & other.0 == other.0
+	}
+}
+
+impl BitOr for JoystickState {
+	type Output = Self;
+
+	fn bitor(self, rhs: Self) -> Self {
+		Self(self.0 | rhs.0)
+	}
+}
+
+impl FromStr for JoystickState {
+	type Err = anyhow::Error;
+
+	/// Accepts `none`, or combinations like `right+fire`.
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		let mut state = JoystickState::NONE;
+		for d in s.split('+') {
+			state = state
+				| match d.trim() {
+					"up" => JoystickState::UP,
+					"down" => JoystickState::DOWN,
+					"left" => JoystickState::LEFT,
+					"right" => JoystickState::RIGHT,
+					"fire" => JoystickState::FIRE,
+					"none" => JoystickState::NONE,
+					d => anyhow::bail!("Invalid joystick direction >{}<", d),
+				};
+		}
+		Ok(state)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn from_str() {
+		assert_eq!(
+			"right+fire".parse::<JoystickState>().unwrap(),
+			JoystickState::RIGHT | JoystickState::FIRE
+		);
+		assert_eq!(" up + left ".parse::<JoystickState>().unwrap().bits(), 0x05);
+		assert_eq!(
+			"none".parse::<JoystickState>().unwrap(),
+			JoystickState::NONE
+		);
+		assert!("north".parse::<JoystickState>().is_err());
+		assert!("up+".parse::<JoystickState>().is_err());
+	}
+
+	#[test]
+	fn bits() {
+		let state = JoystickState::from_bits(0xff);
+		assert_eq!(state.bits(), 0x1f);
+		assert!(state.contains(JoystickState::DOWN | JoystickState::FIRE));
+		assert!(!JoystickState::UP.contains(JoystickState::FIRE));
+	}
+}
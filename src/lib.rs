@@ -1,6 +1,54 @@
 mod fake_vice_bin;
+pub use fake_vice_bin::CheckpointHitCallback;
 pub use fake_vice_bin::FakeViceBin;
+pub use fake_vice_bin::PcCallback;
+pub use fake_vice_bin::Register;
+#[cfg(feature = "async")]
+mod async_fake_vice_bin;
+#[cfg(feature = "async")]
+pub use async_fake_vice_bin::AsyncFakeViceBin;
 mod response_header;
 pub use response_header::ResponseHeader;
 mod response;
 pub use response::Response;
+mod vice_error;
+pub use vice_error::ErrorCode;
+pub use vice_error::ViceError;
+mod decode_error;
+pub use decode_error::DecodeError;
+mod packet_log;
+pub use packet_log::PACKET_TARGET;
+mod petscii;
+pub use petscii::to_petscii;
+mod protocol;
+mod reader;
+mod request;
+mod response_framer;
+pub use request::Request;
+pub use request::ResourceValue;
+mod checkpoint;
+pub use checkpoint::Checkpoint;
+pub use checkpoint::CheckpointOperation;
+mod condition;
+pub mod parse;
+pub use condition::CompareOp;
+pub use condition::ConditionExpr;
+pub use condition::Operand;
+mod screenshot;
+pub use screenshot::Screenshot;
+mod screen_diff;
+pub use screen_diff::Region;
+pub use screen_diff::ScreenDiff;
+mod screen_text;
+pub use screen_text::screen_codes_to_text;
+pub use screen_text::screen_to_text;
+mod joystick_state;
+pub use joystick_state::JoystickState;
+mod vsf;
+pub use vsf::CpuRegisters;
+pub use vsf::Vsf;
+pub use vsf::VsfModule;
+mod event;
+pub use event::Event;
+mod reconnect_policy;
+pub use reconnect_policy::ReconnectPolicy;
@@ -0,0 +1,22 @@
+/// Log target for hex dumps of everything sent to and received from VICE.
+///
+/// Enable with e.g. `RUST_LOG=fake_vice_bin::packets=trace`.
+pub const PACKET_TARGET: &str = "fake_vice_bin::packets";
+
+pub(crate) fn log_sent(bytes: &[u8]) {
+	log_packet("->", bytes);
+}
+pub(crate) fn log_received(bytes: &[u8]) {
+	log_packet("<-", bytes);
+}
+
+fn log_packet(direction: &str, bytes: &[u8]) {
+	if log::log_enabled!(target: PACKET_TARGET, log::Level::Trace) {
+		let hex = bytes
+			.iter()
+			.map(|b| format!("{:02x}", b))
+			.collect::<Vec<_>>()
+			.join(" ");
+		log::trace!(target: PACKET_TARGET, "{} {:5} | {}", direction, bytes.len(), hex);
+	}
+}
@@ -0,0 +1,46 @@
+/// Parses a 16 bit number, accepts `$c000`, `0xc000`, and decimal.
+pub fn parse_number(s: &str) -> anyhow::Result<u16> {
+	let s = s.trim();
+	let v = if let Some(hex) = s.strip_prefix('$') {
+		u16::from_str_radix(hex, 16)
+	} else if let Some(hex) = s.strip_prefix("0x") {
+		u16::from_str_radix(hex, 16)
+	} else {
+		s.parse()
+	};
+	v.map_err(|e| anyhow::anyhow!("Invalid number >{}<: {}", s, e))
+}
+
+/// Finds `op` outside of any parentheses.
+pub fn find_top_level(s: &str, op: &str) -> Option<usize> {
+	let mut depth = 0;
+	for (i, c) in s.char_indices() {
+		match c {
+			'(' => depth += 1,
+			')' => depth -= 1,
+			_ if depth == 0 && s[i..].starts_with(op) => return Some(i),
+			_ => {},
+		}
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn numbers() {
+		assert_eq!(parse_number("$c000").unwrap(), 0xc000);
+		assert_eq!(parse_number(" 0xFFFF ").unwrap(), 0xffff);
+		assert_eq!(parse_number("2049").unwrap(), 0x0801);
+		assert!(parse_number("65536").is_err());
+		assert!(parse_number("$").is_err());
+	}
+
+	#[test]
+	fn top_level() {
+		assert_eq!(find_top_level("(a || b) || c", "||"), Some(9));
+		assert_eq!(find_top_level("(a, b)", ","), None);
+	}
+}
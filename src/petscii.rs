@@ -0,0 +1,112 @@
+// escape name, PETSCII code
+const KEYS: [(&str, u8); 24] = [
+	("RETURN", 0x0d),
+	("SHIFT-RETURN", 0x8d),
+	("STOP", 0x03),
+	("UP", 0x91),
+	("DOWN", 0x11),
+	("LEFT", 0x9d),
+	("RIGHT", 0x1d),
+	("HOME", 0x13),
+	("CLR", 0x93),
+	("DEL", 0x14),
+	("INST", 0x94),
+	("RVS-ON", 0x12),
+	("RVS-OFF", 0x92),
+	("SPACE", 0x20),
+	("SHIFT-SPACE", 0xa0),
+	("F1", 0x85),
+	("F2", 0x89),
+	("F3", 0x86),
+	("F4", 0x8a),
+	("F5", 0x87),
+	("F6", 0x8b),
+	("F7", 0x88),
+	("F8", 0x8c),
+	("PI", 0xde),
+];
+
+/// Converts text to PETSCII, as typed on the keyboard in the default (upper case/graphics) mode.
+///
+/// Letters of either case become unshifted letters, `\n` is RETURN.
+/// Other keys are written as escapes in braces, e.g. `{DOWN}`, `{F1}`, `{SHIFT-A}` for the shifted key,
+/// or `{$93}` for any code.
+pub fn to_petscii(text: &str) -> anyhow::Result<Vec<u8>> {
+	let mut petscii = Vec::with_capacity(text.len());
+	let mut chars = text.chars();
+	while let Some(c) = chars.next() {
+		let code = match c {
+			'{' => {
+				let mut escape = String::new();
+				loop {
+					match chars.next() {
+						Some('}') => break,
+						Some(c) => escape.push(c),
+						None => anyhow::bail!("Missing closing }} on escape >{{{}<", escape),
+					}
+				}
+				escape_to_petscii(&escape)?
+			},
+			'\n' | '\r' => 0x0d,
+			' '..='@' | '[' | ']' => c as u8,
+			'a'..='z' => c.to_ascii_uppercase() as u8,
+			'A'..='Z' => c as u8,
+			'£' => 0x5c,
+			'^' | '↑' => 0x5e,
+			'←' => 0x5f,
+			'π' => 0xde,
+			c => anyhow::bail!("No PETSCII for >{}< ({:?})", c, c),
+		};
+		petscii.push(code);
+	}
+	Ok(petscii)
+}
+
+fn escape_to_petscii(escape: &str) -> anyhow::Result<u8> {
+	let name = escape.trim().to_ascii_uppercase();
+	if let Some((_, code)) = KEYS.iter().find(|(n, _)| *n == name) {
+		return Ok(*code);
+	}
+	if let Some(hex) = name.strip_prefix('$').or_else(|| name.strip_prefix("0X")) {
+		return u8::from_str_radix(hex, 16)
+			.map_err(|e| anyhow::anyhow!("Invalid code in escape >{{{}}}<: {}", escape, e));
+	}
+	if let Some(key) = name.strip_prefix("SHIFT-") {
+		let mut key = key.chars();
+		if let (Some(c @ 'A'..='Z'), None) = (key.next(), key.next()) {
+			return Ok(c as u8 | 0x80);
+		}
+	}
+	anyhow::bail!("Unknown escape >{{{}}}<", escape);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn plain_text() {
+		assert_eq!(
+			to_petscii("LOAD\"*\",8,1\n").unwrap(),
+			b"LOAD\"*\",8,1\r".to_vec()
+		);
+		assert_eq!(to_petscii("run").unwrap(), b"RUN".to_vec());
+		assert_eq!(to_petscii("£↑←").unwrap(), vec![0x5c, 0x5e, 0x5f]);
+	}
+
+	#[test]
+	fn escapes() {
+		assert_eq!(
+			to_petscii("{clr}{DOWN}{F1}{shift-a}{$ff}{return}").unwrap(),
+			vec![0x93, 0x11, 0x85, 0xc1, 0xff, 0x0d]
+		);
+	}
+
+	#[test]
+	fn invalid() {
+		assert!(to_petscii("{DOWN").is_err());
+		assert!(to_petscii("{NOPE}").is_err());
+		assert!(to_petscii("{SHIFT-AB}").is_err());
+		assert!(to_petscii("\t").is_err());
+	}
+}
@@ -0,0 +1,14 @@
+use std::time::Duration;
+
+/// Request id VICE uses for events that are not a response to a request.
+pub(crate) const EVENT_REQUEST_ID: u32 = 0xffffffff;
+/// Largest response body accepted, anything larger is treated as a corrupted header.
+pub(crate) const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+pub(crate) const READ_BUFFER_SIZE: usize = 64 * 1024;
+pub(crate) const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// False for responses that are followed by more for the same request.
+pub(crate) fn completes_request(command: u8, response_type: u8) -> bool {
+	// checkpoint list answers with one info per checkpoint before the list itself
+	command != 0x14 || response_type == 0x14
+}
@@ -0,0 +1,52 @@
+use crate::DecodeError;
+
+/// Bounds checked little endian reader over a packet body.
+pub(crate) struct Reader<'a> {
+	buffer: &'a [u8],
+	pos:    usize,
+}
+
+impl<'a> Reader<'a> {
+	pub fn new(buffer: &'a [u8]) -> Self {
+		Self { buffer, pos: 0 }
+	}
+	pub fn remaining(&self) -> usize {
+		self.buffer.len() - self.pos
+	}
+	pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+		if len > self.remaining() {
+			return Err(DecodeError::Truncated {
+				needed:    self.pos + len,
+				available: self.buffer.len(),
+			});
+		}
+		let bytes = &self.buffer[self.pos..self.pos + len];
+		self.pos += len;
+		Ok(bytes)
+	}
+	pub fn u8(&mut self) -> Result<u8, DecodeError> {
+		Ok(self.bytes(1)?[0])
+	}
+	pub fn bool(&mut self) -> Result<bool, DecodeError> {
+		Ok(self.u8()? != 0)
+	}
+	pub fn u16(&mut self) -> Result<u16, DecodeError> {
+		let b = self.bytes(2)?;
+		Ok(u16::from_le_bytes([b[0], b[1]]))
+	}
+	pub fn u32(&mut self) -> Result<u32, DecodeError> {
+		let b = self.bytes(4)?;
+		Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+	}
+	/// Length prefixed string.
+	pub fn string(&mut self) -> Result<String, DecodeError> {
+		let len = self.u8()? as usize;
+		let bytes = self.bytes(len)?;
+		String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::BadUtf8)
+	}
+	pub fn rest(&mut self) -> &'a [u8] {
+		let rest = &self.buffer[self.pos..];
+		self.pos = self.buffer.len();
+		rest
+	}
+}
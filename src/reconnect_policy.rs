@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+/// How `FakeViceBin` retries after losing the connection to VICE.
+///
+/// The delay starts at `initial_delay` and doubles with every failed attempt, capped at `max_delay`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+	max_attempts:  u32,
+	initial_delay: Duration,
+	max_delay:     Duration,
+}
+
+impl Default for ReconnectPolicy {
+	fn default() -> Self {
+		Self {
+			max_attempts:  10,
+			initial_delay: Duration::from_millis(100),
+			max_delay:     Duration::from_secs(5),
+		}
+	}
+}
+
+impl ReconnectPolicy {
+	pub fn new(max_attempts: u32, initial_delay: Duration, max_delay: Duration) -> Self {
+		Self {
+			max_attempts,
+			initial_delay,
+			max_delay,
+		}
+	}
+
+	pub fn max_attempts(&self) -> u32 {
+		self.max_attempts
+	}
+	pub fn initial_delay(&self) -> Duration {
+		self.initial_delay
+	}
+	pub fn max_delay(&self) -> Duration {
+		self.max_delay
+	}
+
+	/// Delay before the given attempt, counting from 1.
+	pub fn delay(&self, attempt: u32) -> Duration {
+		let factor = 1u32
+			.checked_shl(attempt.saturating_sub(1))
+			.unwrap_or(u32::MAX);
+		self.initial_delay
+			.checked_mul(factor)
+			.unwrap_or(self.max_delay)
+			.min(self.max_delay)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn policy() -> ReconnectPolicy {
+		ReconnectPolicy::new(10, Duration::from_millis(100), Duration::from_secs(1))
+	}
+
+	#[test]
+	fn delay_doubles() {
+		let p = policy();
+		assert_eq!(p.delay(1), Duration::from_millis(100));
+		assert_eq!(p.delay(2), Duration::from_millis(200));
+		assert_eq!(p.delay(3), Duration::from_millis(400));
+		assert_eq!(p.delay(4), Duration::from_millis(800));
+	}
+
+	#[test]
+	fn delay_is_capped() {
+		let p = policy();
+		assert_eq!(p.delay(5), Duration::from_secs(1));
+		assert_eq!(p.delay(p.max_attempts()), Duration::from_secs(1));
+		// no overflow far beyond the attempt limit
+		assert_eq!(p.delay(33), Duration::from_secs(1));
+		assert_eq!(p.delay(u32::MAX), Duration::from_secs(1));
+	}
+
+	#[test]
+	fn delay_before_first_attempt() {
+		assert_eq!(policy().delay(0), Duration::from_millis(100));
+	}
+}
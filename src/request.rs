@@ -0,0 +1,774 @@
+use crate::reader::Reader;
+use crate::CheckpointOperation;
+use crate::DecodeError;
+
+pub const STX: u8 = 0x02;
+pub const API_VERSION: u8 = 0x02;
+
+/// Resource value for `ResourceSet`, VICE resources are either strings or integers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceValue {
+	String(String),
+	Integer(u32),
+}
+
+/// Every command of the VICE binary monitor protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+	MemoryGet {
+		side_effects: bool,
+		start:        u16,
+		end:          u16,
+		memspace:     u8,
+		bank:         u16,
+	},
+	MemorySet {
+		side_effects: bool,
+		start:        u16,
+		memspace:     u8,
+		bank:         u16,
+		bytes:        Vec<u8>,
+	},
+	CheckpointGet {
+		number: u32,
+	},
+	CheckpointSet {
+		start:         u16,
+		end:           u16,
+		stop_when_hit: bool,
+		enabled:       bool,
+		operation:     CheckpointOperation,
+		temporary:     bool,
+	},
+	CheckpointDelete {
+		number: u32,
+	},
+	CheckpointList,
+	CheckpointToggle {
+		number:  u32,
+		enabled: bool,
+	},
+	ConditionSet {
+		checkpoint: u32,
+		condition:  String,
+	},
+	RegistersGet {
+		memspace: u8,
+	},
+	RegistersSet {
+		memspace:  u8,
+		registers: Vec<(u8, u16)>, // id, value
+	},
+	Dump {
+		save_roms:  bool,
+		save_disks: bool,
+		filename:   String,
+	},
+	Undump {
+		filename: String,
+	},
+	ResourceGet {
+		name: String,
+	},
+	ResourceSet {
+		name:  String,
+		value: ResourceValue,
+	},
+	AdvanceInstructions {
+		step_over_subroutines: bool,
+		count:                 u16,
+	},
+	KeyboardFeed {
+		petscii: Vec<u8>,
+	},
+	ExecuteUntilReturn,
+	Ping,
+	BanksAvailable,
+	RegistersAvailable {
+		memspace: u8,
+	},
+	DisplayGet {
+		use_vicii: bool,
+		format:    u8,
+	},
+	ViceInfo,
+	PaletteGet {
+		use_vicii: bool,
+	},
+	JoyportSet {
+		port:  u16,
+		value: u16,
+	},
+	UserportSet {
+		value: u16,
+	},
+	Exit,
+	Quit,
+	Reset {
+		kind: u8,
+	},
+	AutoStart {
+		run:        bool,
+		file_index: u16,
+		filename:   String,
+	},
+}
+
+impl Request {
+	pub fn command(&self) -> u8 {
+		match self {
+			Request::MemoryGet { .. } => 0x01,
+			Request::MemorySet { .. } => 0x02,
+			Request::CheckpointGet { .. } => 0x11,
+			Request::CheckpointSet { .. } => 0x12,
+			Request::CheckpointDelete { .. } => 0x13,
+			Request::CheckpointList => 0x14,
+			Request::CheckpointToggle { .. } => 0x15,
+			Request::ConditionSet { .. } => 0x22,
+			Request::RegistersGet { .. } => 0x31,
+			Request::RegistersSet { .. } => 0x32,
+			Request::Dump { .. } => 0x41,
+			Request::Undump { .. } => 0x42,
+			Request::ResourceGet { .. } => 0x51,
+			Request::ResourceSet { .. } => 0x52,
+			Request::AdvanceInstructions { .. } => 0x71,
+			Request::KeyboardFeed { .. } => 0x72,
+			Request::ExecuteUntilReturn => 0x73,
+			Request::Ping => 0x81,
+			Request::BanksAvailable => 0x82,
+			Request::RegistersAvailable { .. } => 0x83,
+			Request::DisplayGet { .. } => 0x84,
+			Request::ViceInfo => 0x85,
+			Request::PaletteGet { .. } => 0x91,
+			Request::JoyportSet { .. } => 0xa2,
+			Request::UserportSet { .. } => 0xb2,
+			Request::Exit => 0xaa,
+			Request::Quit => 0xbb,
+			Request::Reset { .. } => 0xcc,
+			Request::AutoStart { .. } => 0xdd,
+		}
+	}
+
+	/// Full packet, header and body, ready to be written to the socket.
+	///
+	/// Fails for values the protocol can not express, e.g. strings longer than 255 bytes.
+	pub fn encode(&self, request_id: u32) -> anyhow::Result<Vec<u8>> {
+		let body = self.encode_body()?;
+		let mut buffer = Vec::with_capacity(11 + body.len());
+		buffer.push(STX);
+		buffer.push(API_VERSION);
+		// 2-5 body length
+		buffer.extend_from_slice(&(body.len() as u32).to_le_bytes());
+		// 6-9 request id
+		buffer.extend_from_slice(&request_id.to_le_bytes());
+		// 10 command id
+		buffer.push(self.command());
+		// 11+ command body
+		buffer.extend_from_slice(&body);
+
+		Ok(buffer)
+	}
+
+	pub fn encode_body(&self) -> anyhow::Result<Vec<u8>> {
+		let mut body = Vec::new();
+		match self {
+			Request::MemoryGet {
+				side_effects,
+				start,
+				end,
+				memspace,
+				bank,
+			} => {
+				body.push(*side_effects as u8);
+				body.extend_from_slice(&start.to_le_bytes());
+				body.extend_from_slice(&end.to_le_bytes());
+				body.push(*memspace);
+				body.extend_from_slice(&bank.to_le_bytes());
+			},
+			Request::MemorySet {
+				side_effects,
+				start,
+				memspace,
+				bank,
+				bytes,
+			} => {
+				if bytes.is_empty() {
+					anyhow::bail!("No bytes to set at {:#06x}", start);
+				}
+				let end = *start as usize + bytes.len() - 1;
+				if end > 0xffff {
+					anyhow::bail!(
+						"{} bytes at {:#06x} go beyond the end of memory",
+						bytes.len(),
+						start
+					);
+				}
+				let end = end as u16;
+				body.push(*side_effects as u8);
+				body.extend_from_slice(&start.to_le_bytes());
+				body.extend_from_slice(&end.to_le_bytes());
+				body.push(*memspace);
+				body.extend_from_slice(&bank.to_le_bytes());
+				body.extend_from_slice(bytes);
+			},
+			Request::CheckpointGet { number } | Request::CheckpointDelete { number } => {
+				body.extend_from_slice(&number.to_le_bytes());
+			},
+			Request::CheckpointSet {
+				start,
+				end,
+				stop_when_hit,
+				enabled,
+				operation,
+				temporary,
+			} => {
+				body.extend_from_slice(&start.to_le_bytes());
+				body.extend_from_slice(&end.to_le_bytes());
+				body.push(*stop_when_hit as u8);
+				body.push(*enabled as u8);
+				body.push(operation.bits());
+				body.push(*temporary as u8);
+			},
+			Request::CheckpointToggle { number, enabled } => {
+				body.extend_from_slice(&number.to_le_bytes());
+				body.push(*enabled as u8);
+			},
+			Request::ConditionSet {
+				checkpoint,
+				condition,
+			} => {
+				body.extend_from_slice(&checkpoint.to_le_bytes());
+				push_string(&mut body, condition)?;
+			},
+			Request::RegistersGet { memspace } | Request::RegistersAvailable { memspace } => {
+				body.push(*memspace);
+			},
+			Request::RegistersSet {
+				memspace,
+				registers,
+			} => {
+				let Ok(count) = u16::try_from(registers.len()) else {
+					anyhow::bail!("Too many registers to set ({})", registers.len());
+				};
+				body.push(*memspace);
+				body.extend_from_slice(&count.to_le_bytes());
+				for (id, value) in registers.iter() {
+					body.push(3); // size of item, excluding this byte
+					body.push(*id);
+					body.extend_from_slice(&value.to_le_bytes());
+				}
+			},
+			Request::Dump {
+				save_roms,
+				save_disks,
+				filename,
+			} => {
+				body.push(*save_roms as u8);
+				body.push(*save_disks as u8);
+				push_string(&mut body, filename)?;
+			},
+			Request::Undump { filename } => {
+				push_string(&mut body, filename)?;
+			},
+			Request::ResourceGet { name } => {
+				push_string(&mut body, name)?;
+			},
+			Request::ResourceSet { name, value } => match value {
+				ResourceValue::String(value) => {
+					body.push(0x00);
+					push_string(&mut body, name)?;
+					push_string(&mut body, value)?;
+				},
+				ResourceValue::Integer(value) => {
+					body.push(0x01);
+					push_string(&mut body, name)?;
+					body.push(4);
+					body.extend_from_slice(&value.to_le_bytes());
+				},
+			},
+			Request::AdvanceInstructions {
+				step_over_subroutines,
+				count,
+			} => {
+				body.push(*step_over_subroutines as u8);
+				body.extend_from_slice(&count.to_le_bytes());
+			},
+			Request::KeyboardFeed { petscii } => {
+				let Ok(len) = u8::try_from(petscii.len()) else {
+					anyhow::bail!(
+						"Keyboard feed of {} bytes too long, at most 255",
+						petscii.len()
+					);
+				};
+				body.push(len);
+				body.extend_from_slice(petscii);
+			},
+			Request::DisplayGet { use_vicii, format } => {
+				body.push(*use_vicii as u8);
+				body.push(*format);
+			},
+			Request::PaletteGet { use_vicii } => {
+				body.push(*use_vicii as u8);
+			},
+			Request::JoyportSet { port, value } => {
+				body.extend_from_slice(&port.to_le_bytes());
+				body.extend_from_slice(&value.to_le_bytes());
+			},
+			Request::UserportSet { value } => {
+				body.extend_from_slice(&value.to_le_bytes());
+			},
+			Request::Reset { kind } => {
+				body.push(*kind);
+			},
+			Request::AutoStart {
+				run,
+				file_index,
+				filename,
+			} => {
+				body.push(*run as u8);
+				body.extend_from_slice(&file_index.to_le_bytes());
+				push_string(&mut body, filename)?;
+			},
+			Request::CheckpointList
+			| Request::ExecuteUntilReturn
+			| Request::Ping
+			| Request::BanksAvailable
+			| Request::ViceInfo
+			| Request::Exit
+			| Request::Quit => {},
+		}
+		Ok(body)
+	}
+}
+
+// length prefixed, the protocol limits strings to 255 bytes
+fn push_string(body: &mut Vec<u8>, s: &str) -> anyhow::Result<()> {
+	let Ok(len) = u8::try_from(s.len()) else {
+		anyhow::bail!("String of {} bytes too long, at most 255", s.len());
+	};
+	body.push(len);
+	body.extend_from_slice(s.as_bytes());
+	Ok(())
+}
+
+fn decode_body(command: u8, buffer: &[u8]) -> Result<Request, DecodeError> {
+	let mut r = Reader::new(buffer);
+	let request = match command {
+		0x01 => Request::MemoryGet {
+			side_effects: r.bool()?,
+			start:        r.u16()?,
+			end:          r.u16()?,
+			memspace:     r.u8()?,
+			bank:         r.u16()?,
+		},
+		0x02 => {
+			let side_effects = r.bool()?;
+			let start = r.u16()?;
+			let end = r.u16()?;
+			let memspace = r.u8()?;
+			let bank = r.u16()?;
+			let bytes = r.rest().to_vec();
+			let expected = end.wrapping_sub(start) as usize + 1;
+			if end < start || bytes.len() != expected {
+				return Err(DecodeError::CountMismatch {
+					expected,
+					found: bytes.len(),
+				});
+			}
+			Request::MemorySet {
+				side_effects,
+				start,
+				memspace,
+				bank,
+				bytes,
+			}
+		},
+		0x11 => Request::CheckpointGet { number: r.u32()? },
+		0x12 => Request::CheckpointSet {
+			start:         r.u16()?,
+			end:           r.u16()?,
+			stop_when_hit: r.bool()?,
+			enabled:       r.bool()?,
+			operation:     CheckpointOperation::from_bits(r.u8()?),
+			temporary:     r.bool()?,
+		},
+		0x13 => Request::CheckpointDelete { number: r.u32()? },
+		0x14 => Request::CheckpointList,
+		0x15 => Request::CheckpointToggle {
+			number:  r.u32()?,
+			enabled: r.bool()?,
+		},
+		0x22 => Request::ConditionSet {
+			checkpoint: r.u32()?,
+			condition:  r.string()?,
+		},
+		0x31 => Request::RegistersGet { memspace: r.u8()? },
+		0x32 => {
+			let memspace = r.u8()?;
+			let count = r.u16()?;
+			let mut registers = Vec::with_capacity(count as usize);
+			for _ in 0..count {
+				let size = r.u8()? as usize;
+				let item = r.bytes(size)?;
+				if size < 3 {
+					return Err(DecodeError::Truncated {
+						needed:    3,
+						available: size,
+					});
+				}
+				registers.push((item[0], u16::from_le_bytes([item[1], item[2]])));
+			}
+			Request::RegistersSet {
+				memspace,
+				registers,
+			}
+		},
+		0x41 => Request::Dump {
+			save_roms:  r.bool()?,
+			save_disks: r.bool()?,
+			filename:   r.string()?,
+		},
+		0x42 => Request::Undump {
+			filename: r.string()?,
+		},
+		0x51 => Request::ResourceGet { name: r.string()? },
+		0x52 => {
+			let kind = r.u8()?;
+			let name = r.string()?;
+			let value = match kind {
+				0x00 => ResourceValue::String(r.string()?),
+				0x01 => {
+					let len = r.u8()? as usize;
+					let bytes = r.bytes(len)?;
+					let mut value = 0u32;
+					for b in bytes.iter().rev() {
+						value = value << 8 | *b as u32;
+					}
+					ResourceValue::Integer(value)
+				},
+				t => return Err(DecodeError::UnknownType(t)),
+			};
+			Request::ResourceSet { name, value }
+		},
+		0x71 => Request::AdvanceInstructions {
+			step_over_subroutines: r.bool()?,
+			count:                 r.u16()?,
+		},
+		0x72 => {
+			let len = r.u8()? as usize;
+			Request::KeyboardFeed {
+				petscii: r.bytes(len)?.to_vec(),
+			}
+		},
+		0x73 => Request::ExecuteUntilReturn,
+		0x81 => Request::Ping,
+		0x82 => Request::BanksAvailable,
+		0x83 => Request::RegistersAvailable { memspace: r.u8()? },
+		0x84 => Request::DisplayGet {
+			use_vicii: r.bool()?,
+			format:    r.u8()?,
+		},
+		0x85 => Request::ViceInfo,
+		0x91 => Request::PaletteGet {
+			use_vicii: r.bool()?,
+		},
+		0xa2 => Request::JoyportSet {
+			port:  r.u16()?,
+			value: r.u16()?,
+		},
+		0xb2 => Request::UserportSet { value: r.u16()? },
+		0xaa => Request::Exit,
+		0xbb => Request::Quit,
+		0xcc => Request::Reset { kind: r.u8()? },
+		0xdd => Request::AutoStart {
+			run:        r.bool()?,
+			file_index: r.u16()?,
+			filename:   r.string()?,
+		},
+		c => return Err(DecodeError::UnknownType(c)),
+	};
+	Ok(request)
+}
+
+impl TryFrom<(u8, &[u8])> for Request {
+	type Error = DecodeError;
+
+	fn try_from(parts: (u8, &[u8])) -> Result<Self, Self::Error> {
+		decode_body(parts.0, parts.1)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn round_trip(request: Request) -> Vec<u8> {
+		let packet = request.encode(0x12345678).unwrap();
+		assert_eq!(&packet[0..2], &[STX, API_VERSION]);
+		assert_eq!(&packet[2..6], &((packet.len() - 11) as u32).to_le_bytes());
+		assert_eq!(&packet[6..10], &0x12345678u32.to_le_bytes());
+		assert_eq!(packet[10], request.command());
+		assert_eq!(Request::try_from((packet[10], &packet[11..])), Ok(request));
+		packet[11..].to_vec()
+	}
+
+	#[test]
+	fn memory_get() {
+		let body = round_trip(Request::MemoryGet {
+			side_effects: false,
+			start:        0xc000,
+			end:          0xc0ff,
+			memspace:     0,
+			bank:         1,
+		});
+		assert_eq!(body, vec![0x00, 0x00, 0xc0, 0xff, 0xc0, 0x00, 0x01, 0x00]);
+	}
+
+	#[test]
+	fn memory_set() {
+		let body = round_trip(Request::MemorySet {
+			side_effects: true,
+			start:        0xfffe,
+			memspace:     0,
+			bank:         0,
+			bytes:        vec![0xa9, 0x00],
+		});
+		assert_eq!(&body[1..5], &[0xfe, 0xff, 0xff, 0xff]); // start, end
+	}
+
+	#[test]
+	fn memory_set_length_mismatch() {
+		// $c000 - $c002, but only two bytes
+		let body = [0x00, 0x00, 0xc0, 0x02, 0xc0, 0x00, 0x00, 0x00, 0xa9, 0x00];
+		assert_eq!(
+			Request::try_from((0x02, &body[..])),
+			Err(DecodeError::CountMismatch {
+				expected: 3,
+				found:    2,
+			})
+		);
+		// end before start
+		let body = [0x00, 0x01, 0xc0, 0x00, 0xc0, 0x00, 0x00, 0x00, 0xa9];
+		assert!(Request::try_from((0x02, &body[..])).is_err());
+	}
+
+	#[test]
+	fn memory_set_out_of_range() {
+		let memory_set = |start, len| Request::MemorySet {
+			side_effects: false,
+			start,
+			memspace: 0,
+			bank: 0,
+			bytes: vec![0; len],
+		};
+		assert!(memory_set(0xc000, 0).encode(1).is_err());
+		assert!(memory_set(0xffff, 2).encode(1).is_err());
+		assert!(memory_set(0x0000, 0x10001).encode(1).is_err());
+		assert!(memory_set(0x0000, 0x10000).encode(1).is_ok());
+	}
+
+	#[test]
+	fn checkpoint_get() {
+		round_trip(Request::CheckpointGet { number: 3 });
+	}
+
+	#[test]
+	fn checkpoint_set() {
+		let body = round_trip(Request::CheckpointSet {
+			start:         0x080d,
+			end:           0x0810,
+			stop_when_hit: true,
+			enabled:       true,
+			operation:     CheckpointOperation::LOAD | CheckpointOperation::STORE,
+			temporary:     false,
+		});
+		assert_eq!(body, vec![0x0d, 0x08, 0x10, 0x08, 0x01, 0x01, 0x03, 0x00]);
+	}
+
+	#[test]
+	fn checkpoint_delete() {
+		round_trip(Request::CheckpointDelete { number: 0x01020304 });
+	}
+
+	#[test]
+	fn checkpoint_list() {
+		assert!(round_trip(Request::CheckpointList).is_empty());
+	}
+
+	#[test]
+	fn checkpoint_toggle() {
+		round_trip(Request::CheckpointToggle {
+			number:  2,
+			enabled: false,
+		});
+	}
+
+	#[test]
+	fn condition_set() {
+		let body = round_trip(Request::ConditionSet {
+			checkpoint: 1,
+			condition:  "A == $10".to_owned(),
+		});
+		assert_eq!(body[4], 8);
+	}
+
+	#[test]
+	fn registers_get() {
+		round_trip(Request::RegistersGet { memspace: 0 });
+	}
+
+	#[test]
+	fn registers_set() {
+		let body = round_trip(Request::RegistersSet {
+			memspace:  0,
+			registers: vec![(0x03, 0xc000), (0x00, 0x42)],
+		});
+		assert_eq!(&body[1..7], &[0x02, 0x00, 0x03, 0x03, 0x00, 0xc0]);
+	}
+
+	#[test]
+	fn dump() {
+		round_trip(Request::Dump {
+			save_roms:  false,
+			save_disks: true,
+			filename:   "state.vsf".to_owned(),
+		});
+	}
+
+	#[test]
+	fn undump() {
+		round_trip(Request::Undump {
+			filename: "state.vsf".to_owned(),
+		});
+	}
+
+	#[test]
+	fn resource_get() {
+		round_trip(Request::ResourceGet {
+			name: "WarpMode".to_owned(),
+		});
+	}
+
+	#[test]
+	fn resource_set() {
+		round_trip(Request::ResourceSet {
+			name:  "KernalName".to_owned(),
+			value: ResourceValue::String("kernal".to_owned()),
+		});
+		let body = round_trip(Request::ResourceSet {
+			name:  "WarpMode".to_owned(),
+			value: ResourceValue::Integer(1),
+		});
+		assert_eq!(&body[10..], &[0x04, 0x01, 0x00, 0x00, 0x00]);
+	}
+
+	#[test]
+	fn advance_instructions_count() {
+		let body = round_trip(Request::AdvanceInstructions {
+			step_over_subroutines: true,
+			count:                 0x1234,
+		});
+		assert_eq!(body, vec![0x01, 0x34, 0x12]);
+	}
+
+	#[test]
+	fn keyboard_feed() {
+		let body = round_trip(Request::KeyboardFeed {
+			petscii: b"RUN\r".to_vec(),
+		});
+		assert_eq!(body[0], 4);
+		round_trip(Request::KeyboardFeed {
+			petscii: vec![0x41; 0xff],
+		});
+		let too_long = Request::KeyboardFeed {
+			petscii: vec![0x41; 0x100],
+		};
+		assert!(too_long.encode(1).is_err());
+	}
+
+	#[test]
+	fn execute_until_return() {
+		round_trip(Request::ExecuteUntilReturn);
+	}
+
+	#[test]
+	fn ping() {
+		round_trip(Request::Ping);
+	}
+
+	#[test]
+	fn banks_available() {
+		round_trip(Request::BanksAvailable);
+	}
+
+	#[test]
+	fn registers_available() {
+		round_trip(Request::RegistersAvailable { memspace: 1 });
+	}
+
+	#[test]
+	fn display_get() {
+		round_trip(Request::DisplayGet {
+			use_vicii: true,
+			format:    0,
+		});
+	}
+
+	#[test]
+	fn vice_info() {
+		round_trip(Request::ViceInfo);
+	}
+
+	#[test]
+	fn palette_get() {
+		round_trip(Request::PaletteGet { use_vicii: true });
+	}
+
+	#[test]
+	fn joyport_set() {
+		round_trip(Request::JoyportSet {
+			port:  1,
+			value: 0x10,
+		});
+	}
+
+	#[test]
+	fn userport_set() {
+		round_trip(Request::UserportSet { value: 0xff });
+	}
+
+	#[test]
+	fn exit() {
+		round_trip(Request::Exit);
+	}
+
+	#[test]
+	fn quit() {
+		round_trip(Request::Quit);
+	}
+
+	#[test]
+	fn reset() {
+		round_trip(Request::Reset { kind: 1 });
+	}
+
+	#[test]
+	fn auto_start() {
+		round_trip(Request::AutoStart {
+			run:        true,
+			file_index: 0,
+			filename:   "game.prg".to_owned(),
+		});
+	}
+
+	#[test]
+	fn string_too_long() {
+		round_trip(Request::Undump {
+			filename: "a".repeat(0xff),
+		});
+		let too_long = Request::Undump {
+			filename: "a".repeat(0x100),
+		};
+		assert!(too_long.encode(1).is_err());
+	}
+}
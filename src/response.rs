@@ -1,14 +1,60 @@
 use std::collections::HashMap;
 
+use crate::reader::Reader;
+use crate::Checkpoint;
+use crate::CheckpointOperation;
+use crate::DecodeError;
+use crate::ResourceValue;
 use crate::ResponseHeader;
 
+// from debug width up to and including the buffer length
+const DISPLAY_FIELDS_LEN: u32 = 17;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Response {
 	RegistersGet {
-		registers: HashMap<u8, (u8, u16)>, // id -> size, value
+		registers: HashMap<u8, u16>, // id -> value
 	},
 	RegistersAvailable {
 		registers: HashMap<u8, (u8, String)>, // id -> size, name
 	},
+	Display {
+		debug_width:    u16, // full buffer, including what is never visible
+		debug_height:   u16,
+		offset_x:       u16, // visible area inside the buffer
+		offset_y:       u16,
+		inner_width:    u16,
+		inner_height:   u16,
+		bits_per_pixel: u8,
+		buffer:         Vec<u8>,
+	},
+	Palette {
+		colors: Vec<(u8, u8, u8)>, // r, g, b
+	},
+	MemoryGet {
+		bytes: Vec<u8>,
+	},
+	MemorySet,
+	CheckpointInfo {
+		checkpoint: Checkpoint,
+	},
+	CheckpointDelete,
+	CheckpointList {
+		count: u32,
+	},
+	CheckpointToggle,
+	ConditionSet,
+	Dump,
+	Undump {
+		pc: u16,
+	},
+	ResourceGet {
+		value: ResourceValue,
+	},
+	ResourceSet,
+	Jam {
+		pc: u16,
+	},
 	Stopped {
 		pc: u16,
 	},
@@ -16,76 +62,412 @@ pub enum Response {
 		pc: u16,
 	},
 	AdvanceInstructions,
+	KeyboardFeed,
+	ExecuteUntilReturn,
 	Ping,
-	Reset,
+	BanksAvailable {
+		banks: HashMap<u16, String>, // id -> name
+	},
+	ViceInfo {
+		version:      (u8, u8, u8, u8), // major, minor, build, patch
+		svn_revision: u32,
+	},
+	JoyportSet,
+	UserportSet,
 	Exit,
+	Quit,
+	Reset,
+	AutoStart,
 	Invalid,
 }
 
-impl From<(&ResponseHeader, &[u8])> for Response {
-	fn from(parts: (&ResponseHeader, &[u8])) -> Self {
+impl Response {
+	pub fn response_type(&self) -> u8 {
+		match self {
+			Response::MemoryGet { .. } => 0x01,
+			Response::MemorySet => 0x02,
+			Response::CheckpointInfo { .. } => 0x11,
+			Response::CheckpointDelete => 0x13,
+			Response::CheckpointList { .. } => 0x14,
+			Response::CheckpointToggle => 0x15,
+			Response::ConditionSet => 0x22,
+			Response::RegistersGet { .. } => 0x31,
+			Response::Dump => 0x41,
+			Response::Undump { .. } => 0x42,
+			Response::ResourceGet { .. } => 0x51,
+			Response::ResourceSet => 0x52,
+			Response::Jam { .. } => 0x61,
+			Response::Stopped { .. } => 0x62,
+			Response::Resumed { .. } => 0x63,
+			Response::AdvanceInstructions => 0x71,
+			Response::KeyboardFeed => 0x72,
+			Response::ExecuteUntilReturn => 0x73,
+			Response::Ping => 0x81,
+			Response::BanksAvailable { .. } => 0x82,
+			Response::RegistersAvailable { .. } => 0x83,
+			Response::Display { .. } => 0x84,
+			Response::ViceInfo { .. } => 0x85,
+			Response::Palette { .. } => 0x91,
+			Response::JoyportSet => 0xa2,
+			Response::UserportSet => 0xb2,
+			Response::Exit => 0xaa,
+			Response::Quit => 0xbb,
+			Response::Reset => 0xcc,
+			Response::AutoStart => 0xdd,
+			Response::Invalid => 0x00,
+		}
+	}
+
+	/// Full packet, header and body, as VICE would send it.
+	pub fn encode(&self, request_id: u32) -> Vec<u8> {
+		let body = self.encode_body();
+		let error_code = match self {
+			Response::Invalid => 0x8f, // general failure
+			_ => 0x00,
+		};
+		let rh = ResponseHeader::new(
+			body.len() as u32,
+			self.response_type(),
+			error_code,
+			request_id,
+		);
+		let mut buffer = Vec::with_capacity(12 + body.len());
+		buffer.extend_from_slice(&rh.encode());
+		buffer.extend_from_slice(&body);
+
+		buffer
+	}
+
+	pub fn encode_body(&self) -> Vec<u8> {
+		let mut body = Vec::new();
+		match self {
+			Response::MemoryGet { bytes } => {
+				// the full 64KB are sent with a length of 0
+				let len = if bytes.len() == 0x10000 {
+					0
+				} else {
+					bytes.len() as u16
+				};
+				body.extend_from_slice(&len.to_le_bytes());
+				body.extend_from_slice(bytes);
+			},
+			Response::CheckpointInfo { checkpoint } => {
+				body.extend_from_slice(&checkpoint.number().to_le_bytes());
+				body.push(checkpoint.hit() as u8);
+				body.extend_from_slice(&checkpoint.start().to_le_bytes());
+				body.extend_from_slice(&checkpoint.end().to_le_bytes());
+				body.push(checkpoint.stop_when_hit() as u8);
+				body.push(checkpoint.enabled() as u8);
+				body.push(checkpoint.operation().bits());
+				body.push(checkpoint.temporary() as u8);
+				body.extend_from_slice(&checkpoint.hit_count().to_le_bytes());
+				body.extend_from_slice(&checkpoint.ignore_count().to_le_bytes());
+				body.push(checkpoint.has_condition() as u8);
+				body.push(checkpoint.memspace());
+			},
+			Response::CheckpointList { count } => {
+				body.extend_from_slice(&count.to_le_bytes());
+			},
+			Response::RegistersGet { registers } => {
+				let mut ids = registers.keys().collect::<Vec<_>>();
+				ids.sort();
+				body.extend_from_slice(&(ids.len() as u16).to_le_bytes());
+				for id in ids {
+					let value = registers[id];
+					body.push(3); // size of item, excluding this byte
+					body.push(*id);
+					body.extend_from_slice(&value.to_le_bytes());
+				}
+			},
+			Response::RegistersAvailable { registers } => {
+				let mut ids = registers.keys().collect::<Vec<_>>();
+				ids.sort();
+				body.extend_from_slice(&(ids.len() as u16).to_le_bytes());
+				for id in ids {
+					let (size, name) = &registers[id];
+					let name = &name.as_bytes()[..name.len().min(0xff - 3)];
+					body.push(3 + name.len() as u8); // size of item, excluding this byte
+					body.push(*id);
+					body.push(*size);
+					body.push(name.len() as u8);
+					body.extend_from_slice(name);
+				}
+			},
+			Response::Display {
+				debug_width,
+				debug_height,
+				offset_x,
+				offset_y,
+				inner_width,
+				inner_height,
+				bits_per_pixel,
+				buffer,
+			} => {
+				body.extend_from_slice(&DISPLAY_FIELDS_LEN.to_le_bytes());
+				body.extend_from_slice(&debug_width.to_le_bytes());
+				body.extend_from_slice(&debug_height.to_le_bytes());
+				body.extend_from_slice(&offset_x.to_le_bytes());
+				body.extend_from_slice(&offset_y.to_le_bytes());
+				body.extend_from_slice(&inner_width.to_le_bytes());
+				body.extend_from_slice(&inner_height.to_le_bytes());
+				body.push(*bits_per_pixel);
+				body.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+				body.extend_from_slice(buffer);
+			},
+			Response::ResourceGet { value } => match value {
+				ResourceValue::String(value) => {
+					let value = &value.as_bytes()[..value.len().min(0xff)];
+					body.push(0x00);
+					body.push(value.len() as u8);
+					body.extend_from_slice(value);
+				},
+				ResourceValue::Integer(value) => {
+					body.push(0x01);
+					body.push(4);
+					body.extend_from_slice(&value.to_le_bytes());
+				},
+			},
+			Response::BanksAvailable { banks } => {
+				let mut ids = banks.keys().collect::<Vec<_>>();
+				ids.sort();
+				body.extend_from_slice(&(ids.len() as u16).to_le_bytes());
+				for id in ids {
+					let name = &banks[id];
+					let name = &name.as_bytes()[..name.len().min(0xff - 3)];
+					body.push(3 + name.len() as u8); // size of item, excluding this byte
+					body.extend_from_slice(&id.to_le_bytes());
+					body.push(name.len() as u8);
+					body.extend_from_slice(name);
+				}
+			},
+			Response::ViceInfo {
+				version,
+				svn_revision,
+			} => {
+				body.push(4);
+				body.extend_from_slice(&[version.0, version.1, version.2, version.3]);
+				body.push(4);
+				body.extend_from_slice(&svn_revision.to_le_bytes());
+			},
+			Response::Palette { colors } => {
+				body.extend_from_slice(&(colors.len() as u16).to_le_bytes());
+				for (r, g, b) in colors {
+					body.push(3); // size of item, excluding this byte
+					body.extend_from_slice(&[*r, *g, *b]);
+				}
+			},
+			Response::Jam { pc }
+			| Response::Stopped { pc }
+			| Response::Resumed { pc }
+			| Response::Undump { pc } => {
+				body.extend_from_slice(&pc.to_le_bytes());
+			},
+			Response::MemorySet
+			| Response::CheckpointDelete
+			| Response::CheckpointToggle
+			| Response::ConditionSet
+			| Response::Dump
+			| Response::ResourceSet
+			| Response::AdvanceInstructions
+			| Response::KeyboardFeed
+			| Response::ExecuteUntilReturn
+			| Response::Ping
+			| Response::JoyportSet
+			| Response::UserportSet
+			| Response::Exit
+			| Response::Quit
+			| Response::Reset
+			| Response::AutoStart
+			| Response::Invalid => {},
+		}
+		body
+	}
+}
+
+impl TryFrom<(&ResponseHeader, &[u8])> for Response {
+	type Error = DecodeError;
+
+	fn try_from(parts: (&ResponseHeader, &[u8])) -> Result<Self, Self::Error> {
 		let rh = parts.0;
-		let buffer = parts.1;
-		match rh.response_type() {
+		let mut r = Reader::new(parts.1);
+		if rh.error_code() != 0x00 {
+			// error responses carry no usable body
+			return Ok(Response::Invalid);
+		}
+		let response = match rh.response_type() {
+			0x01 => {
+				// memory get
+				let len = match r.u16()? as usize {
+					0 if r.remaining() == 0x10000 => 0x10000, // the full 64KB
+					len => len,
+				};
+				if len != r.remaining() {
+					return Err(DecodeError::CountMismatch {
+						expected: len,
+						found:    r.remaining(),
+					});
+				}
+				let bytes = r.bytes(len)?.to_vec();
+				Response::MemoryGet { bytes }
+			},
+			0x02 => {
+				// memory set
+				Response::MemorySet
+			},
+			0x11 => {
+				// checkpoint info
+				let checkpoint = Checkpoint::new(
+					r.u32()?,
+					r.bool()?,
+					r.u16()?,
+					r.u16()?,
+					r.bool()?,
+					r.bool()?,
+					CheckpointOperation::from_bits(r.u8()?),
+					r.bool()?,
+					r.u32()?,
+					r.u32()?,
+					r.bool()?,
+					r.u8()?,
+				);
+				Response::CheckpointInfo { checkpoint }
+			},
+			0x13 => {
+				// checkpoint delete
+				Response::CheckpointDelete
+			},
+			0x14 => {
+				// checkpoint list
+				let count = r.u32()?;
+				Response::CheckpointList { count }
+			},
+			0x15 => {
+				// checkpoint toggle
+				Response::CheckpointToggle
+			},
+			0x22 => {
+				// condition set
+				Response::ConditionSet
+			},
 			0x31 => {
 				// registers get
-				let c = [buffer[1], buffer[0]];
-				let mut count = 0;
-				for b in c.iter() {
-					count <<= 8;
-					count |= *b as u16;
-				}
-				//println!("Count {}", count);
-				let mut entry_start = 2;
+				/*
+				byte 0-1: The count of the array items
+				byte 2+: An array with items of structure:
+
+				byte 0: Size of the item, excluding this byte
+				byte 1: ID of the register
+				byte 2-3: register value
+				*/
+				let count = r.u16()? as usize;
 				let mut registers = HashMap::new();
 				for e in 0..count {
-					let size = buffer[entry_start];
-					let id = buffer[entry_start + 1];
-					let value =
-						((buffer[entry_start + 3] as u16) << 8) | (buffer[entry_start + 2] as u16);
-
-					let r = (size, value);
-					registers.insert(id, r);
-					entry_start += 4;
+					let item = Self::item(&mut r, 3, count, e)?;
+					let id = item[0];
+					let value = u16::from_le_bytes([item[1], item[2]]);
+					registers.insert(id, value);
 				}
 				Response::RegistersGet { registers }
 			},
+			0x41 => {
+				// dump
+				Response::Dump
+			},
+			0x42 => {
+				// undump
+				let pc = r.u16()?;
+				Response::Undump { pc }
+			},
+			0x51 => {
+				// resource get
+				/*
+				byte 0: Type of the value, 0x00 string, 0x01 integer
+				byte 1: Length of the value
+				byte 2+: Value, integers are little endian
+				*/
+				let kind = r.u8()?;
+				let len = r.u8()? as usize;
+				let bytes = r.bytes(len)?;
+				let value = match kind {
+					0x00 => ResourceValue::String(
+						std::str::from_utf8(bytes)
+							.map_err(|_| DecodeError::BadUtf8)?
+							.to_owned(),
+					),
+					0x01 => {
+						let mut value = 0u32;
+						for b in bytes.iter().take(4).rev() {
+							value = value << 8 | *b as u32;
+						}
+						ResourceValue::Integer(value)
+					},
+					t => return Err(DecodeError::UnknownType(t)),
+				};
+				Response::ResourceGet { value }
+			},
+			0x52 => {
+				// resource set
+				Response::ResourceSet
+			},
+			0x61 => {
+				// jam
+				let pc = r.u16()?;
+				Response::Jam { pc }
+			},
 			0x62 => {
 				// stopped
-				let c = [buffer[1], buffer[0]];
-				let mut pc = 0;
-				for b in c.iter() {
-					pc <<= 8;
-					pc |= *b as u16;
-				}
+				let pc = r.u16()?;
 				Response::Stopped { pc }
 			},
 			0x63 => {
 				// resumed
-				let c = [buffer[1], buffer[0]];
-				let mut pc = 0;
-				for b in c.iter() {
-					pc <<= 8;
-					pc |= *b as u16;
-				}
+				let pc = r.u16()?;
 				Response::Resumed { pc }
 			},
 			0x71 => {
 				// advance instructions
-				Response::AdvanceInstructions // :TODO:
+				Response::AdvanceInstructions
+			},
+			0x72 => {
+				// keyboard feed
+				Response::KeyboardFeed
+			},
+			0x73 => {
+				// execute until return
+				Response::ExecuteUntilReturn
 			},
 			0x81 => {
 				// ping
 				Response::Ping
 			},
+			0x82 => {
+				// banks available
+				/*
+				byte 0-1: The count of the array items
+				byte 2+: An array with items of structure:
+
+				byte 0: Size of the item, excluding this byte
+				byte 1-2: ID of the bank
+				byte 3: Length of name
+				byte 4+: Name
+				*/
+				let count = r.u16()? as usize;
+				let mut banks = HashMap::new();
+				for e in 0..count {
+					let item = Self::item(&mut r, 3, count, e)?;
+					let id = u16::from_le_bytes([item[0], item[1]]);
+					let len = item[2] as usize;
+					let name = item.get(3..3 + len).ok_or(DecodeError::Truncated {
+						needed:    3 + len,
+						available: item.len(),
+					})?;
+					let name = std::str::from_utf8(name).map_err(|_| DecodeError::BadUtf8)?;
+					banks.insert(id, name.to_owned());
+				}
+				Response::BanksAvailable { banks }
+			},
 			0x83 => {
 				// registers available
-				println!("Body for 0x83 - registers available");
-				for b in buffer.iter() {
-					print!("{:#02x} ", b);
-				}
-				println!("");
-
 				/*
 				byte 0-1: The count of the array items
 				byte 2+: An array with items of structure:
@@ -95,47 +477,566 @@ impl From<(&ResponseHeader, &[u8])> for Response {
 				byte 2: Size of the register in bits
 				byte 3: Length of name
 				byte 4+: Name
-									*/
-
+				*/
+				let count = r.u16()? as usize;
 				let mut registers = HashMap::new();
-				let count = (buffer[1] as u16) << 8 | (buffer[0] as u16);
-				println!("Entry count {}", count);
-
-				let mut entry_start = 2;
 				for e in 0..count {
-					let size = buffer[entry_start] as usize;
-					let id = buffer[entry_start + 1];
-					let r_size = buffer[entry_start + 2];
-					let len = buffer[entry_start + 3] as usize;
-					let mut name = Vec::new();
-					for i in 0..=len {
-						name.push(buffer[entry_start + 3 + i])
-					}
-
-					let name = match std::str::from_utf8(&name) {
-						Ok(name) => name,
-						Err(_e) => "[INVALID]",
-					};
-
-					println!(
-						"{:#02} | {:#04x} {:#04x} {:#04x} -> {}",
-						e, size, id, r_size, name
-					);
-					let r = (r_size, name.to_owned());
-					registers.insert(id, r);
-					entry_start += size + 1;
+					let item = Self::item(&mut r, 3, count, e)?;
+					let id = item[0];
+					let r_size = item[1];
+					let len = item[2] as usize;
+					let name = item.get(3..3 + len).ok_or(DecodeError::Truncated {
+						needed:    3 + len,
+						available: item.len(),
+					})?;
+					let name = std::str::from_utf8(name).map_err(|_| DecodeError::BadUtf8)?;
+
+					log::trace!("{:#02} | {:#04x} {:#04x} -> {}", e, id, r_size, name);
+					registers.insert(id, (r_size, name.to_owned()));
 				}
 				Response::RegistersAvailable { registers }
 			},
+			0x84 => {
+				// display get
+				/*
+				byte 0-3: Length of the fields before the display buffer
+				byte 4-5: Debug width of display buffer (uncropped)
+				byte 6-7: Debug height of display buffer (uncropped)
+				byte 8-9: X offset to the inner part of the screen
+				byte 10-11: Y offset to the inner part of the screen
+				byte 12-13: Width of the inner part of the screen
+				byte 14-15: Height of the inner part of the screen
+				byte 16: Bits per pixel of display buffer, 8
+				byte 17-20: Length of display buffer
+				byte 21+: Display buffer data
+				*/
+				let fields_len = r.u32()? as usize;
+				// newer versions might add fields
+				let mut f = Reader::new(r.bytes(fields_len)?);
+				let debug_width = f.u16()?;
+				let debug_height = f.u16()?;
+				let offset_x = f.u16()?;
+				let offset_y = f.u16()?;
+				let inner_width = f.u16()?;
+				let inner_height = f.u16()?;
+				let bits_per_pixel = f.u8()?;
+				let len = f.u32()? as usize;
+				let buffer = r.bytes(len)?.to_vec();
+				Response::Display {
+					debug_width,
+					debug_height,
+					offset_x,
+					offset_y,
+					inner_width,
+					inner_height,
+					bits_per_pixel,
+					buffer,
+				}
+			},
+			0x85 => {
+				// vice info
+				/*
+				byte 0: Length of the version, 4
+				byte 1-4: Version, major, minor, build, patch
+				byte 5: Length of the svn revision, 4
+				byte 6-9: Svn revision, 0 for releases
+				*/
+				let len = r.u8()? as usize;
+				let v = r.bytes(len)?;
+				if v.len() < 4 {
+					return Err(DecodeError::Truncated {
+						needed:    4,
+						available: v.len(),
+					});
+				}
+				let version = (v[0], v[1], v[2], v[3]);
+				let len = r.u8()? as usize;
+				let mut f = Reader::new(r.bytes(len)?);
+				let svn_revision = f.u32()?;
+				Response::ViceInfo {
+					version,
+					svn_revision,
+				}
+			},
+			0x91 => {
+				// palette get
+				let count = r.u16()? as usize;
+				let mut colors = Vec::with_capacity(count);
+				for e in 0..count {
+					let item = Self::item(&mut r, 3, count, e)?;
+					colors.push((item[0], item[1], item[2]));
+				}
+				Response::Palette { colors }
+			},
+			0xa2 => {
+				// joyport set
+				Response::JoyportSet
+			},
+			0xb2 => {
+				// userport set
+				Response::UserportSet
+			},
 			0xaa => {
 				// exit
 				Response::Exit
 			},
+			0xbb => {
+				// quit
+				Response::Quit
+			},
 			0xcc => {
 				// reset
 				Response::Reset
 			},
-			o => Response::Invalid,
+			0xdd => {
+				// auto start
+				Response::AutoStart
+			},
+			t => return Err(DecodeError::UnknownType(t)),
+		};
+		Ok(response)
+	}
+}
+
+impl Response {
+	// one size prefixed array item, `e` of `count`
+	fn item<'a>(
+		r: &mut Reader<'a>,
+		min_size: usize,
+		count: usize,
+		e: usize,
+	) -> Result<&'a [u8], DecodeError> {
+		if r.remaining() == 0 {
+			return Err(DecodeError::CountMismatch {
+				expected: count,
+				found:    e,
+			});
 		}
+		let size = r.u8()? as usize;
+		if size < min_size {
+			return Err(DecodeError::Truncated {
+				needed:    min_size,
+				available: size,
+			});
+		}
+		r.bytes(size)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn decode(packet: &[u8]) -> Result<Response, DecodeError> {
+		let header: &[u8; 12] = packet[0..12].try_into().unwrap();
+		let rh = ResponseHeader::from(header);
+		assert!(rh.valid());
+		assert_eq!(rh.body_len() as usize, packet.len() - 12);
+		Response::try_from((&rh, &packet[12..]))
+	}
+
+	fn decode_body(response_type: u8, body: &[u8]) -> Result<Response, DecodeError> {
+		let rh = ResponseHeader::new(body.len() as u32, response_type, 0x00, 1);
+		Response::try_from((&rh, body))
+	}
+
+	fn round_trip(response: Response) {
+		let packet = response.encode(0x12345678);
+		assert_eq!(&packet[8..12], &0x12345678u32.to_le_bytes());
+		assert_eq!(decode(&packet), Ok(response));
+	}
+
+	#[test]
+	fn memory_get() {
+		let r = decode_body(0x01, &[0x03, 0x00, 0xa9, 0x00, 0x60]).unwrap();
+		assert_eq!(
+			r,
+			Response::MemoryGet {
+				bytes: vec![0xa9, 0x00, 0x60],
+			}
+		);
+		round_trip(r);
+	}
+
+	#[test]
+	fn memory_get_64k() {
+		let r = Response::MemoryGet {
+			bytes: (0..=0xffff).map(|a| a as u8).collect(),
+		};
+		let body = r.encode_body();
+		assert_eq!(&body[..2], &[0x00, 0x00]);
+		assert_eq!(decode_body(0x01, &body), Ok(r.clone()));
+		round_trip(r);
+	}
+
+	#[test]
+	fn memory_get_length_mismatch() {
+		assert_eq!(
+			decode_body(0x01, &[0x04, 0x00, 0xa9, 0x00]),
+			Err(DecodeError::CountMismatch {
+				expected: 4,
+				found:    2,
+			})
+		);
+	}
+
+	#[test]
+	fn memory_set() {
+		assert_eq!(decode_body(0x02, &[]), Ok(Response::MemorySet));
+		round_trip(Response::MemorySet);
+	}
+
+	#[test]
+	fn checkpoint_info() {
+		let body = [
+			0x02, 0x00, 0x00, 0x00, // number
+			0x01, // hit
+			0x00, 0xc0, 0x10, 0xc0, // start, end
+			0x01, 0x01, 0x04, 0x00, // stop, enabled, exec, temporary
+			0x05, 0x00, 0x00, 0x00, // hit count
+			0x00, 0x00, 0x00, 0x00, // ignore count
+			0x01, 0x00, // has condition, memspace
+		];
+		let r = decode_body(0x11, &body).unwrap();
+		match &r {
+			Response::CheckpointInfo { checkpoint } => {
+				assert_eq!(checkpoint.number(), 2);
+				assert!(checkpoint.hit());
+				assert_eq!(checkpoint.start(), 0xc000);
+				assert_eq!(checkpoint.end(), 0xc010);
+				assert!(checkpoint.stop_when_hit());
+				assert!(checkpoint.enabled());
+				assert_eq!(checkpoint.operation(), CheckpointOperation::EXEC);
+				assert!(!checkpoint.temporary());
+				assert_eq!(checkpoint.hit_count(), 5);
+				assert!(checkpoint.has_condition());
+			},
+			r => panic!("Unexpected {:?}", r),
+		}
+		round_trip(r);
+	}
+
+	#[test]
+	fn checkpoint_info_truncated() {
+		assert_eq!(
+			decode_body(0x11, &[0x02, 0x00, 0x00, 0x00, 0x01, 0x00]),
+			Err(DecodeError::Truncated {
+				needed:    7,
+				available: 6,
+			})
+		);
+	}
+
+	#[test]
+	fn checkpoint_delete() {
+		assert_eq!(decode_body(0x13, &[]), Ok(Response::CheckpointDelete));
+		round_trip(Response::CheckpointDelete);
+	}
+
+	#[test]
+	fn checkpoint_list() {
+		let r = decode_body(0x14, &[0x03, 0x00, 0x00, 0x00]).unwrap();
+		assert_eq!(r, Response::CheckpointList { count: 3 });
+		round_trip(r);
+		assert!(matches!(
+			decode_body(0x14, &[0x03]),
+			Err(DecodeError::Truncated { .. })
+		));
+	}
+
+	#[test]
+	fn checkpoint_toggle() {
+		assert_eq!(decode_body(0x15, &[]), Ok(Response::CheckpointToggle));
+		round_trip(Response::CheckpointToggle);
+	}
+
+	#[test]
+	fn condition_set() {
+		assert_eq!(decode_body(0x22, &[]), Ok(Response::ConditionSet));
+		round_trip(Response::ConditionSet);
+	}
+
+	#[test]
+	fn registers_get() {
+		let body = [
+			0x02, 0x00, // count
+			0x03, 0x00, 0x40, 0x00, // A = $40
+			0x03, 0x03, 0x00, 0xc0, // PC = $c000
+		];
+		let r = decode_body(0x31, &body).unwrap();
+		assert_eq!(
+			r,
+			Response::RegistersGet {
+				registers: HashMap::from([(0x00, 0x0040), (0x03, 0xc000)]),
+			}
+		);
+		round_trip(r);
+	}
+
+	#[test]
+	fn registers_get_count_mismatch() {
+		let body = [
+			0x02, 0x00, // count
+			0x03, 0x00, 0x40, 0x00, // A = $40
+		];
+		assert_eq!(
+			decode_body(0x31, &body),
+			Err(DecodeError::CountMismatch {
+				expected: 2,
+				found:    1,
+			})
+		);
+	}
+
+	#[test]
+	fn registers_available() {
+		let body = [
+			0x02, 0x00, // count
+			0x04, 0x00, 0x08, 0x01, b'A', // A, 8 bits
+			0x05, 0x03, 0x10, 0x02, b'P', b'C', // PC, 16 bits
+		];
+		let r = decode_body(0x83, &body).unwrap();
+		assert_eq!(
+			r,
+			Response::RegistersAvailable {
+				registers: HashMap::from([
+					(0x00, (8, "A".to_owned())),
+					(0x03, (16, "PC".to_owned())),
+				]),
+			}
+		);
+		round_trip(r);
+	}
+
+	#[test]
+	fn registers_available_bad_utf8() {
+		let body = [0x01, 0x00, 0x04, 0x00, 0x08, 0x01, 0xff];
+		assert_eq!(decode_body(0x83, &body), Err(DecodeError::BadUtf8));
+	}
+
+	#[test]
+	fn registers_available_name_truncated() {
+		let body = [0x01, 0x00, 0x04, 0x00, 0x08, 0x05, b'A'];
+		assert!(matches!(
+			decode_body(0x83, &body),
+			Err(DecodeError::Truncated { .. })
+		));
+	}
+
+	#[test]
+	fn jam() {
+		let r = decode_body(0x61, &[0x02, 0xc0]).unwrap();
+		assert_eq!(r, Response::Jam { pc: 0xc002 });
+		round_trip(r);
+	}
+
+	#[test]
+	fn stopped() {
+		let r = decode_body(0x62, &[0x34, 0x12]).unwrap();
+		assert_eq!(r, Response::Stopped { pc: 0x1234 });
+		round_trip(r);
+		assert!(matches!(
+			decode_body(0x62, &[0x34]),
+			Err(DecodeError::Truncated { .. })
+		));
+	}
+
+	#[test]
+	fn resumed() {
+		let r = decode_body(0x63, &[0x00, 0xe0]).unwrap();
+		assert_eq!(r, Response::Resumed { pc: 0xe000 });
+		round_trip(r);
+	}
+
+	#[test]
+	fn advance_instructions() {
+		assert_eq!(decode_body(0x71, &[]), Ok(Response::AdvanceInstructions));
+		round_trip(Response::AdvanceInstructions);
+	}
+
+	#[test]
+	fn display() {
+		let r = Response::Display {
+			debug_width:    4,
+			debug_height:   3,
+			offset_x:       1,
+			offset_y:       1,
+			inner_width:    2,
+			inner_height:   1,
+			bits_per_pixel: 8,
+			buffer:         (0..12).collect(),
+		};
+		assert_eq!(&r.encode_body()[..4], &[17, 0, 0, 0]);
+		round_trip(r);
+
+		let mut truncated = Response::Display {
+			debug_width:    4,
+			debug_height:   3,
+			offset_x:       0,
+			offset_y:       0,
+			inner_width:    4,
+			inner_height:   3,
+			bits_per_pixel: 8,
+			buffer:         vec![0; 12],
+		}
+		.encode_body();
+		truncated.pop();
+		assert!(matches!(
+			decode_body(0x84, &truncated),
+			Err(DecodeError::Truncated { .. })
+		));
+	}
+
+	#[test]
+	fn palette() {
+		let r = decode_body(
+			0x91,
+			&[0x02, 0x00, 0x03, 0x00, 0x00, 0x00, 0x03, 0xff, 0xff, 0xff],
+		)
+		.unwrap();
+		assert_eq!(
+			r,
+			Response::Palette {
+				colors: vec![(0x00, 0x00, 0x00), (0xff, 0xff, 0xff)],
+			}
+		);
+		round_trip(r);
+	}
+
+	#[test]
+	fn dump_and_undump() {
+		assert_eq!(decode_body(0x41, &[]), Ok(Response::Dump));
+		round_trip(Response::Dump);
+		let r = decode_body(0x42, &[0x0d, 0x08]).unwrap();
+		assert_eq!(r, Response::Undump { pc: 0x080d });
+		round_trip(r);
+	}
+
+	#[test]
+	fn execute_until_return() {
+		assert_eq!(decode_body(0x73, &[]), Ok(Response::ExecuteUntilReturn));
+		round_trip(Response::ExecuteUntilReturn);
+	}
+
+	#[test]
+	fn keyboard_feed() {
+		assert_eq!(decode_body(0x72, &[]), Ok(Response::KeyboardFeed));
+		round_trip(Response::KeyboardFeed);
+	}
+
+	#[test]
+	fn joyport_and_userport_set() {
+		assert_eq!(decode_body(0xa2, &[]), Ok(Response::JoyportSet));
+		assert_eq!(decode_body(0xb2, &[]), Ok(Response::UserportSet));
+		round_trip(Response::JoyportSet);
+		round_trip(Response::UserportSet);
+	}
+
+	#[test]
+	fn ping() {
+		assert_eq!(decode_body(0x81, &[]), Ok(Response::Ping));
+		round_trip(Response::Ping);
+	}
+
+	#[test]
+	fn exit() {
+		assert_eq!(decode_body(0xaa, &[]), Ok(Response::Exit));
+		round_trip(Response::Exit);
+	}
+
+	#[test]
+	fn reset() {
+		assert_eq!(decode_body(0xcc, &[]), Ok(Response::Reset));
+		round_trip(Response::Reset);
+	}
+
+	#[test]
+	fn resource_get() {
+		let r = decode_body(0x51, &[0x00, 0x03, b'a', b'b', b'c']).unwrap();
+		assert_eq!(
+			r,
+			Response::ResourceGet {
+				value: ResourceValue::String("abc".to_owned()),
+			}
+		);
+		round_trip(r);
+		let r = decode_body(0x51, &[0x01, 0x04, 0x01, 0x00, 0x00, 0x00]).unwrap();
+		assert_eq!(
+			r,
+			Response::ResourceGet {
+				value: ResourceValue::Integer(1),
+			}
+		);
+		round_trip(r);
+		assert_eq!(
+			decode_body(0x51, &[0x02, 0x00]),
+			Err(DecodeError::UnknownType(0x02))
+		);
+	}
+
+	#[test]
+	fn resource_set() {
+		assert_eq!(decode_body(0x52, &[]), Ok(Response::ResourceSet));
+		round_trip(Response::ResourceSet);
+	}
+
+	#[test]
+	fn banks_available() {
+		let body = [
+			0x02, 0x00, // count
+			0x06, 0x00, 0x00, 0x03, b'c', b'p', b'u', // default
+			0x06, 0x01, 0x00, 0x03, b'r', b'a', b'm', //
+		];
+		let r = decode_body(0x82, &body).unwrap();
+		match &r {
+			Response::BanksAvailable { banks } => {
+				assert_eq!(banks.len(), 2);
+				assert_eq!(banks[&0x0001], "ram");
+			},
+			r => panic!("Unexpected {:?}", r),
+		}
+		round_trip(r);
+	}
+
+	#[test]
+	fn vice_info() {
+		let body = [0x04, 0x03, 0x07, 0x01, 0x00, 0x04, 0x2a, 0x00, 0x00, 0x00];
+		let r = decode_body(0x85, &body).unwrap();
+		assert_eq!(
+			r,
+			Response::ViceInfo {
+				version:      (3, 7, 1, 0),
+				svn_revision: 42,
+			}
+		);
+		round_trip(r);
+		assert!(matches!(
+			decode_body(0x85, &[0x02, 0x03, 0x07]),
+			Err(DecodeError::Truncated { .. })
+		));
+	}
+
+	#[test]
+	fn quit() {
+		assert_eq!(decode_body(0xbb, &[]), Ok(Response::Quit));
+		round_trip(Response::Quit);
+	}
+
+	#[test]
+	fn auto_start() {
+		assert_eq!(decode_body(0xdd, &[]), Ok(Response::AutoStart));
+		round_trip(Response::AutoStart);
+	}
+
+	#[test]
+	fn error_response_is_invalid() {
+		let rh = ResponseHeader::new(0, 0x01, 0x81, 1);
+		assert_eq!(Response::try_from((&rh, &[][..])), Ok(Response::Invalid));
+		round_trip(Response::Invalid);
+	}
+
+	#[test]
+	fn unknown_type() {
+		assert_eq!(decode_body(0x7f, &[]), Err(DecodeError::UnknownType(0x7f)));
 	}
 }
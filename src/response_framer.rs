@@ -0,0 +1,196 @@
+use crate::request::API_VERSION;
+use crate::request::STX;
+use crate::DecodeError;
+use crate::Response;
+use crate::ResponseHeader;
+
+const HEADER_LEN: usize = 12;
+
+#[derive(Debug)]
+pub(crate) enum Framed {
+	Response {
+		header:   ResponseHeader,
+		response: Result<Response, DecodeError>,
+	},
+	/// Bytes skipped to find the next plausible header.
+	Dropped(usize),
+}
+
+/// Cuts the byte stream from VICE into decoded responses.
+///
+/// The buffer grows as needed for large responses, consumed bytes are only dropped in `push`.
+#[derive(Debug)]
+pub(crate) struct ResponseFramer {
+	buffer:       Vec<u8>,
+	start:        usize, // first unconsumed byte
+	max_body_len: usize,
+}
+
+impl ResponseFramer {
+	pub fn new(max_body_len: usize) -> Self {
+		Self {
+			buffer: Vec::new(),
+			start: 0,
+			max_body_len,
+		}
+	}
+
+	pub fn push(&mut self, bytes: &[u8]) {
+		if self.start == self.buffer.len() {
+			self.buffer.clear();
+			self.start = 0;
+		} else if self.start > self.buffer.len() / 2 {
+			self.buffer.drain(..self.start);
+			self.start = 0;
+		}
+		self.buffer.extend_from_slice(bytes);
+	}
+
+	fn pending(&self) -> &[u8] {
+		&self.buffer[self.start..]
+	}
+
+	// any error code is fine, unknown ones reach the caller as `ErrorCode::Other`
+	fn is_plausible(&self, rh: &ResponseHeader) -> bool {
+		if !rh.valid() || rh.body_len() as usize > self.max_body_len {
+			return false;
+		}
+		// type 0x00 is only used for errors, which come without a body
+		rh.response_type() != 0x00 || (rh.error_code() != 0x00 && rh.body_len() == 0)
+	}
+
+	/// Drops bytes up to the next STX/version pair, returns the number of bytes dropped.
+	fn resync(&mut self) -> usize {
+		let pending = self.pending();
+		let mut dropped = 1;
+		while dropped < pending.len() {
+			match pending.get(dropped + 1) {
+				_ if pending[dropped] != STX => dropped += 1,
+				Some(&API_VERSION) | None => break, // None: wait for more data
+				Some(_) => dropped += 1,
+			}
+		}
+		self.start += dropped;
+		dropped
+	}
+
+	/// Next complete response, `None` until enough data arrived.
+	pub fn next(&mut self) -> Option<Framed> {
+		let pending = self.pending();
+		if pending.len() < HEADER_LEN {
+			return None;
+		}
+		let mut header_buffer = [0u8; HEADER_LEN];
+		header_buffer.copy_from_slice(&pending[..HEADER_LEN]);
+		let header: ResponseHeader = (&header_buffer).into();
+		if !self.is_plausible(&header) {
+			return Some(Framed::Dropped(self.resync()));
+		}
+
+		let end = HEADER_LEN + header.body_len() as usize;
+		if pending.len() < end {
+			return None;
+		}
+		let response = Response::try_from((&header, &pending[HEADER_LEN..end]));
+		self.start += end;
+		Some(Framed::Response { header, response })
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::ErrorCode;
+
+	fn framer() -> ResponseFramer {
+		ResponseFramer::new(64 * 1024)
+	}
+
+	fn ping(request_id: u32) -> Vec<u8> {
+		Response::Ping.encode(request_id)
+	}
+
+	fn expect_response(framed: Option<Framed>) -> (ResponseHeader, Response) {
+		match framed {
+			Some(Framed::Response { header, response }) => (header, response.unwrap()),
+			f => panic!("Expected response, got {:?}", f),
+		}
+	}
+
+	#[test]
+	fn complete_packet() {
+		let mut f = framer();
+		f.push(&ping(7));
+		let (header, response) = expect_response(f.next());
+		assert_eq!(header.request_id(), 7);
+		assert_eq!(response, Response::Ping);
+		assert!(f.next().is_none());
+	}
+
+	#[test]
+	fn split_packet() {
+		let bytes = Response::Stopped { pc: 0xc000 }.encode(0xffffffff);
+		let mut f = framer();
+		for b in &bytes[..bytes.len() - 1] {
+			f.push(&[*b]);
+			assert!(f.next().is_none());
+		}
+		f.push(&bytes[bytes.len() - 1..]);
+		let (_, response) = expect_response(f.next());
+		assert_eq!(response, Response::Stopped { pc: 0xc000 });
+	}
+
+	#[test]
+	fn resync_after_garbage() {
+		let mut f = framer();
+		f.push(&[0xde, 0xad, 0x02, 0x01]);
+		f.push(&ping(1));
+		assert!(matches!(f.next(), Some(Framed::Dropped(4))));
+		let (header, _) = expect_response(f.next());
+		assert_eq!(header.request_id(), 1);
+	}
+
+	#[test]
+	fn resync_skips_misaligned_stx() {
+		// trailing STX right before the real header looks like STX + version
+		let mut f = framer();
+		f.push(&[0x02]);
+		f.push(&Response::Stopped { pc: 0xc000 }.encode(0xffffffff));
+		assert!(matches!(f.next(), Some(Framed::Dropped(1))));
+		let (_, response) = expect_response(f.next());
+		assert_eq!(response, Response::Stopped { pc: 0xc000 });
+	}
+
+	#[test]
+	fn unknown_type_is_skipped() {
+		let mut f = framer();
+		let mut bytes = ResponseHeader::new(3, 0x7f, 0x00, 3).encode().to_vec();
+		bytes.extend_from_slice(b"xyz");
+		f.push(&bytes);
+		f.push(&ping(4));
+		match f.next() {
+			Some(Framed::Response { response, .. }) => {
+				assert_eq!(response, Err(DecodeError::UnknownType(0x7f)))
+			},
+			f => panic!("Expected response, got {:?}", f),
+		}
+		let (header, _) = expect_response(f.next());
+		assert_eq!(header.request_id(), 4);
+	}
+
+	#[test]
+	fn unknown_error_code() {
+		let mut f = framer();
+		f.push(&ResponseHeader::new(0, 0x00, 0x84, 5).encode());
+		f.push(&ping(6));
+		let (header, response) = expect_response(f.next());
+		assert_eq!(header.request_id(), 5);
+		assert_eq!(
+			ErrorCode::from_code(header.error_code()),
+			Some(ErrorCode::Other(0x84))
+		);
+		assert_eq!(response, Response::Invalid);
+		let (header, _) = expect_response(f.next());
+		assert_eq!(header.request_id(), 6);
+	}
+}
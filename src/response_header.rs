@@ -1,3 +1,6 @@
+use crate::request::API_VERSION;
+use crate::request::STX;
+
 #[derive(Debug, Default)]
 pub struct ResponseHeader {
 	valid:         bool,
@@ -10,6 +13,29 @@ pub struct ResponseHeader {
 }
 
 impl ResponseHeader {
+	pub fn new(body_len: u32, response_type: u8, error_code: u8, request_id: u32) -> Self {
+		Self {
+			valid: true,
+			stx: STX,
+			version: API_VERSION,
+			body_len,
+			response_type,
+			error_code,
+			request_id,
+		}
+	}
+
+	pub fn encode(&self) -> [u8; 12] {
+		let mut buffer = [0u8; 12];
+		buffer[0] = self.stx;
+		buffer[1] = self.version;
+		buffer[2..6].copy_from_slice(&self.body_len.to_le_bytes());
+		buffer[6] = self.response_type;
+		buffer[7] = self.error_code;
+		buffer[8..12].copy_from_slice(&self.request_id.to_le_bytes());
+		buffer
+	}
+
 	pub fn valid(&self) -> bool {
 		self.valid
 	}
@@ -34,28 +60,20 @@ impl ResponseHeader {
 }
 impl From<&[u8; 12]> for ResponseHeader {
 	fn from(buffer: &[u8; 12]) -> Self {
-		let mut rh = ResponseHeader::default();
-		rh.valid = true;
-		rh.stx = buffer[0];
-		rh.version = buffer[1];
-
-		rh.body_len = (buffer[2] as u32) << 0
-			| (buffer[3] as u32) << 8
-			| (buffer[4] as u32) << 16
-			| (buffer[5] as u32) << 24;
-
-		rh.response_type = buffer[6];
-		rh.error_code = buffer[7];
-
-		rh.request_id = (buffer[8] as u32) << 0
-			| (buffer[9] as u32) << 8
-			| (buffer[10] as u32) << 16
-			| (buffer[11] as u32) << 24;
+		let mut rh = ResponseHeader {
+			valid:         true,
+			stx:           buffer[0],
+			version:       buffer[1],
+			body_len:      u32::from_le_bytes([buffer[2], buffer[3], buffer[4], buffer[5]]),
+			response_type: buffer[6],
+			error_code:    buffer[7],
+			request_id:    u32::from_le_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]),
+		};
 
-		if rh.stx != 0x02 {
+		if rh.stx != STX {
 			rh.valid = false;
 		}
-		if rh.version != 0x02 {
+		if rh.version != API_VERSION {
 			rh.valid = false;
 		}
 		rh
@@ -0,0 +1,146 @@
+use crate::Screenshot;
+
+/// Rectangle in screenshot pixels, e.g. a clock or score ignored when comparing.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+	x:      u32,
+	y:      u32,
+	width:  u32,
+	height: u32,
+}
+
+impl Region {
+	pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+		Self {
+			x,
+			y,
+			width,
+			height,
+		}
+	}
+
+	pub fn x(&self) -> u32 {
+		self.x
+	}
+	pub fn y(&self) -> u32 {
+		self.y
+	}
+	pub fn width(&self) -> u32 {
+		self.width
+	}
+	pub fn height(&self) -> u32 {
+		self.height
+	}
+	pub fn contains(&self, x: u32, y: u32) -> bool {
+		x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+	}
+}
+
+/// Result of comparing a screenshot against a golden image.
+#[derive(Debug, Clone)]
+pub struct ScreenDiff {
+	differing_pixels: usize,
+	max_difference:   u8,
+	image:            Screenshot,
+}
+
+impl ScreenDiff {
+	/// Pixels match if no channel differs by more than `tolerance`, pixels inside `masks` are ignored.
+	///
+	/// The diff image shows matching pixels dimmed, differing ones red, and masked ones black.
+	pub fn compare(
+		actual: &Screenshot,
+		golden: &Screenshot,
+		tolerance: u8,
+		masks: &[Region],
+	) -> anyhow::Result<Self> {
+		if (actual.width(), actual.height()) != (golden.width(), golden.height()) {
+			anyhow::bail!(
+				"Screen is {}x{}, golden image is {}x{}",
+				actual.width(),
+				actual.height(),
+				golden.width(),
+				golden.height()
+			);
+		}
+		let mut differing_pixels = 0;
+		let mut max_difference = 0;
+		let mut rgb = Vec::with_capacity(actual.rgb().len());
+		for y in 0..actual.height() {
+			for x in 0..actual.width() {
+				if masks.iter().any(|m| m.contains(x, y)) {
+					rgb.extend_from_slice(&[0, 0, 0]);
+					continue;
+				}
+				let (ar, ag, ab) = actual.pixel(x, y);
+				let (gr, gg, gb) = golden.pixel(x, y);
+				let difference = ar.abs_diff(gr).max(ag.abs_diff(gg)).max(ab.abs_diff(gb));
+				max_difference = max_difference.max(difference);
+				if difference > tolerance {
+					differing_pixels += 1;
+					rgb.extend_from_slice(&[0xff, 0x00, 0x00]);
+				} else {
+					rgb.extend_from_slice(&[ar / 3, ag / 3, ab / 3]);
+				}
+			}
+		}
+		Ok(Self {
+			differing_pixels,
+			max_difference,
+			image: Screenshot::new(actual.width(), actual.height(), rgb)?,
+		})
+	}
+
+	pub fn matches(&self) -> bool {
+		self.differing_pixels == 0
+	}
+	pub fn differing_pixels(&self) -> usize {
+		self.differing_pixels
+	}
+	/// Largest channel difference outside the masks, also for pixels within tolerance.
+	pub fn max_difference(&self) -> u8 {
+		self.max_difference
+	}
+	pub fn image(&self) -> &Screenshot {
+		&self.image
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn screen(pixels: &[(u8, u8, u8)]) -> Screenshot {
+		let rgb = pixels.iter().flat_map(|(r, g, b)| [*r, *g, *b]).collect();
+		Screenshot::new(pixels.len() as u32, 1, rgb).unwrap()
+	}
+
+	#[test]
+	fn tolerance() {
+		let golden = screen(&[(0x10, 0x10, 0x10), (0x80, 0x80, 0x80)]);
+		let actual = screen(&[(0x14, 0x10, 0x10), (0x80, 0x80, 0x80)]);
+		let diff = ScreenDiff::compare(&actual, &golden, 4, &[]).unwrap();
+		assert!(diff.matches());
+		assert_eq!(diff.max_difference(), 4);
+		let diff = ScreenDiff::compare(&actual, &golden, 3, &[]).unwrap();
+		assert_eq!(diff.differing_pixels(), 1);
+		assert_eq!(diff.image().pixel(0, 0), (0xff, 0x00, 0x00));
+	}
+
+	#[test]
+	fn masks() {
+		let golden = screen(&[(0, 0, 0), (0, 0, 0), (0, 0, 0)]);
+		let actual = screen(&[(0, 0, 0), (0xff, 0xff, 0xff), (0, 0, 0)]);
+		let mask = Region::new(1, 0, 1, 1);
+		let diff = ScreenDiff::compare(&actual, &golden, 0, &[mask]).unwrap();
+		assert!(diff.matches());
+		assert_eq!(diff.max_difference(), 0);
+	}
+
+	#[test]
+	fn size_mismatch() {
+		let golden = screen(&[(0, 0, 0)]);
+		let actual = screen(&[(0, 0, 0), (0, 0, 0)]);
+		assert!(ScreenDiff::compare(&actual, &golden, 0, &[]).is_err());
+	}
+}
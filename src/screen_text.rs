@@ -0,0 +1,89 @@
+pub const SCREEN_COLUMNS: usize = 40;
+pub const SCREEN_ROWS: usize = 25;
+
+// screen codes $40-$7f of the upper case/graphics set
+const UPPER_GRAPHICS: [char; 64] = [
+	'─', '♠', '🭲', '🭸', '🭷', '🭶', '🭺', '🭱', '🭴', '╮', '╰', '╯', '🭼', '╲', '╱', '🭽', //
+	'🭾', '●', '🭻', '♥', '🭰', '╭', '╳', '○', '♣', '🭵', '♦', '┼', '🮌', '│', 'π', '◥', //
+	'\u{a0}', '▌', '▄', '▔', '▁', '▏', '▒', '▕', '🮏', '◤', '🮇', '├', '▗', '└', '┐', '▂', //
+	'┌', '┴', '┬', '┤', '▎', '▍', '🮈', '🮂', '🮃', '▃', '🭿', '▖', '▝', '┘', '▘', '▚', //
+];
+
+/// Converts screen codes, as found in screen RAM, to text.
+///
+/// Reversed characters ($80-$ff) convert like their normal counterparts,
+/// `lower_case` selects the lower/upper case character set instead of upper case/graphics.
+pub fn screen_codes_to_text(codes: &[u8], lower_case: bool) -> String {
+	codes
+		.iter()
+		.map(|code| screen_code_to_char(*code, lower_case))
+		.collect()
+}
+
+fn screen_code_to_char(code: u8, lower_case: bool) -> char {
+	let code = code & 0x7f;
+	match code {
+		0x00 => '@',
+		0x01..=0x1a if lower_case => (b'a' + code - 0x01) as char,
+		0x01..=0x1a => (b'A' + code - 0x01) as char,
+		0x1b => '[',
+		0x1c => '£',
+		0x1d => ']',
+		0x1e => '↑',
+		0x1f => '←',
+		0x20..=0x3f => code as char,
+		0x41..=0x5a if lower_case => (b'A' + code - 0x41) as char,
+		0x5e if lower_case => '🮕',
+		0x5f if lower_case => '🮘',
+		0x69 if lower_case => '🮙',
+		0x7a if lower_case => '✓',
+		_ => UPPER_GRAPHICS[code as usize - 0x40],
+	}
+}
+
+/// Screen codes of a full screen, one line of text per row, trailing spaces removed.
+pub fn screen_to_text(codes: &[u8], lower_case: bool) -> String {
+	codes
+		.chunks(SCREEN_COLUMNS)
+		.map(|row| {
+			screen_codes_to_text(row, lower_case)
+				.trim_end_matches(' ')
+				.to_owned()
+		})
+		.collect::<Vec<_>>()
+		.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn upper_case() {
+		// READY. followed by a reversed space, the cursor
+		assert_eq!(
+			screen_codes_to_text(&[0x12, 0x05, 0x01, 0x04, 0x19, 0x2e, 0xa0], false),
+			"READY. "
+		);
+		assert_eq!(
+			screen_codes_to_text(&[0x00, 0x1c, 0x40, 0x5e], false),
+			"@£─π"
+		);
+	}
+
+	#[test]
+	fn lower_case() {
+		assert_eq!(
+			screen_codes_to_text(&[0x52, 0x05, 0x01, 0x04, 0x19, 0x2e], true),
+			"Ready."
+		);
+	}
+
+	#[test]
+	fn rows() {
+		let mut screen = vec![0x20; SCREEN_COLUMNS * 2];
+		screen[0] = 0x01;
+		screen[SCREEN_COLUMNS + 1] = 0x02;
+		assert_eq!(screen_to_text(&screen, false), "A\n B");
+	}
+}
@@ -0,0 +1,263 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+
+use crate::Response;
+
+/// RGB image of the visible screen area, see `FakeViceBin::screenshot`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Screenshot {
+	width:  u32,
+	height: u32,
+	rgb:    Vec<u8>, // 3 bytes per pixel, row by row
+}
+
+impl Screenshot {
+	pub fn new(width: u32, height: u32, rgb: Vec<u8>) -> anyhow::Result<Self> {
+		if rgb.len() != width as usize * height as usize * 3 {
+			anyhow::bail!(
+				"Expected {} bytes for {}x{} RGB, got {}",
+				width as usize * height as usize * 3,
+				width,
+				height,
+				rgb.len()
+			);
+		}
+		Ok(Self { width, height, rgb })
+	}
+
+	/// Crops the `Display` response to the visible area and looks up the colors in `palette`.
+	pub fn from_display(display: &Response, palette: &[(u8, u8, u8)]) -> anyhow::Result<Self> {
+		let Response::Display {
+			debug_width,
+			debug_height,
+			offset_x,
+			offset_y,
+			inner_width,
+			inner_height,
+			bits_per_pixel,
+			buffer,
+		} = display
+		else {
+			anyhow::bail!("Expected display, got {:?}", display);
+		};
+		// :TODO: VICE only sends indexed 8 bit for now
+		if *bits_per_pixel != 8 {
+			anyhow::bail!("Unsupported bits per pixel {}", bits_per_pixel);
+		}
+		let (debug_width, debug_height) = (*debug_width as usize, *debug_height as usize);
+		let (x, y) = (*offset_x as usize, *offset_y as usize);
+		let (width, height) = (*inner_width as usize, *inner_height as usize);
+		if buffer.len() < debug_width * debug_height
+			|| x + width > debug_width
+			|| y + height > debug_height
+		{
+			anyhow::bail!(
+				"Visible area {}x{} at {},{} outside of {}x{} buffer ({} bytes)",
+				width,
+				height,
+				x,
+				y,
+				debug_width,
+				debug_height,
+				buffer.len()
+			);
+		}
+
+		let mut rgb = Vec::with_capacity(width * height * 3);
+		for row in buffer[y * debug_width..].chunks(debug_width).take(height) {
+			for index in &row[x..x + width] {
+				let Some((r, g, b)) = palette.get(*index as usize) else {
+					anyhow::bail!("Color {} not in palette of {} colors", index, palette.len());
+				};
+				rgb.extend_from_slice(&[*r, *g, *b]);
+			}
+		}
+		Self::new(width as u32, height as u32, rgb)
+	}
+
+	pub fn width(&self) -> u32 {
+		self.width
+	}
+	pub fn height(&self) -> u32 {
+		self.height
+	}
+	pub fn rgb(&self) -> &[u8] {
+		&self.rgb
+	}
+	pub fn pixel(&self, x: u32, y: u32) -> (u8, u8, u8) {
+		let i = (y as usize * self.width as usize + x as usize) * 3;
+		(self.rgb[i], self.rgb[i + 1], self.rgb[i + 2])
+	}
+
+	/// Reads PNG or PPM, depending on the extension of `path`, e.g. a golden image.
+	pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+		let path = path.as_ref();
+		let file = File::open(path)
+			.map_err(|e| anyhow::anyhow!("Error opening {}: {}", path.display(), e))?;
+		let reader = BufReader::new(file);
+		match path.extension().and_then(|e| e.to_str()) {
+			Some(e) if e.eq_ignore_ascii_case("png") => Self::read_png(reader),
+			Some(e) if e.eq_ignore_ascii_case("ppm") => Self::read_ppm(reader),
+			_ => anyhow::bail!(
+				"Unknown image format for {}, use .png or .ppm",
+				path.display()
+			),
+		}
+		.map_err(|e| anyhow::anyhow!("Error reading {}: {}", path.display(), e))
+	}
+
+	fn read_png(reader: impl Read) -> anyhow::Result<Self> {
+		let mut decoder = png::Decoder::new(reader);
+		// palette, gray, and 16 bit all end up as 8 bit per channel
+		decoder.set_transformations(png::Transformations::normalize_to_color8());
+		let mut reader = decoder.read_info()?;
+		let mut buffer = vec![0; reader.output_buffer_size()];
+		let info = reader.next_frame(&mut buffer)?;
+		buffer.truncate(info.buffer_size());
+		let rgb = match info.color_type {
+			png::ColorType::Rgb => buffer,
+			png::ColorType::Rgba => buffer.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+			png::ColorType::Grayscale => buffer.iter().flat_map(|g| [*g, *g, *g]).collect(),
+			png::ColorType::GrayscaleAlpha => {
+				buffer.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect()
+			},
+			c => anyhow::bail!("Unsupported color type {:?}", c),
+		};
+		Self::new(info.width, info.height, rgb)
+	}
+
+	// binary P6 with 8 bit per channel, as written by write_ppm
+	fn read_ppm(mut reader: impl Read) -> anyhow::Result<Self> {
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data)?;
+		// magic, width, height, max value, each followed by whitespace, comments start with #
+		let mut fields = Vec::new();
+		let mut pos = 0;
+		while fields.len() < 4 {
+			while pos < data.len() && (data[pos].is_ascii_whitespace() || data[pos] == b'#') {
+				if data[pos] == b'#' {
+					while pos < data.len() && data[pos] != b'\n' {
+						pos += 1;
+					}
+				} else {
+					pos += 1;
+				}
+			}
+			let start = pos;
+			while pos < data.len() && !data[pos].is_ascii_whitespace() {
+				pos += 1;
+			}
+			if start == pos {
+				anyhow::bail!("Truncated PPM header");
+			}
+			fields.push(std::str::from_utf8(&data[start..pos])?.to_owned());
+		}
+		if fields[0] != "P6" || fields[3] != "255" {
+			anyhow::bail!("Only binary PPM (P6) with 8 bit per channel is supported");
+		}
+		let width = fields[1].parse()?;
+		let height = fields[2].parse()?;
+		// exactly one whitespace before the pixels
+		Self::new(
+			width,
+			height,
+			data.get(pos + 1..).unwrap_or_default().to_vec(),
+		)
+	}
+
+	/// Writes PNG or PPM, depending on the extension of `path`.
+	pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+		let path = path.as_ref();
+		match path.extension().and_then(|e| e.to_str()) {
+			Some(e) if e.eq_ignore_ascii_case("png") => self.write_png(path),
+			Some(e) if e.eq_ignore_ascii_case("ppm") => self.write_ppm(path),
+			_ => anyhow::bail!(
+				"Unknown image format for {}, use .png or .ppm",
+				path.display()
+			),
+		}
+	}
+
+	pub fn write_png(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+		let path = path.as_ref();
+		let file = File::create(path)
+			.map_err(|e| anyhow::anyhow!("Error creating {}: {}", path.display(), e))?;
+		let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+		encoder.set_color(png::ColorType::Rgb);
+		encoder.set_depth(png::BitDepth::Eight);
+		let mut writer = encoder.write_header()?;
+		writer.write_image_data(&self.rgb)?;
+		writer.finish()?;
+		Ok(())
+	}
+
+	/// Binary PPM (P6), no compression.
+	pub fn write_ppm(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+		let path = path.as_ref();
+		let file = File::create(path)
+			.map_err(|e| anyhow::anyhow!("Error creating {}: {}", path.display(), e))?;
+		let mut writer = BufWriter::new(file);
+		write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+		writer.write_all(&self.rgb)?;
+		writer.flush()?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn crops_to_visible_area() {
+		// 4x3 buffer, the 2x1 visible area starts at 1,1
+		let display = Response::Display {
+			debug_width:    4,
+			debug_height:   3,
+			offset_x:       1,
+			offset_y:       1,
+			inner_width:    2,
+			inner_height:   1,
+			bits_per_pixel: 8,
+			buffer:         vec![0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0],
+		};
+		let palette = [(0, 0, 0), (0xff, 0, 0), (0, 0, 0xff)];
+		let s = Screenshot::from_display(&display, &palette).unwrap();
+		assert_eq!((s.width(), s.height()), (2, 1));
+		assert_eq!(s.pixel(0, 0), (0xff, 0, 0));
+		assert_eq!(s.pixel(1, 0), (0, 0, 0xff));
+	}
+
+	#[test]
+	fn save_and_load() {
+		let s = Screenshot::new(2, 1, vec![1, 2, 3, 4, 5, 6]).unwrap();
+		for name in [
+			"fake_vice_bin_screenshot.png",
+			"fake_vice_bin_screenshot.ppm",
+		] {
+			let path = std::env::temp_dir().join(name);
+			s.save(&path).unwrap();
+			assert_eq!(Screenshot::load(&path).unwrap(), s);
+			std::fs::remove_file(path).unwrap();
+		}
+	}
+
+	#[test]
+	fn color_outside_palette() {
+		let display = Response::Display {
+			debug_width:    1,
+			debug_height:   1,
+			offset_x:       0,
+			offset_y:       0,
+			inner_width:    1,
+			inner_height:   1,
+			bits_per_pixel: 8,
+			buffer:         vec![16],
+		};
+		assert!(Screenshot::from_display(&display, &[(0, 0, 0); 16]).is_err());
+	}
+}
@@ -1,15 +1,43 @@
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufRead};
+use std::path::Path;
 use std::str::FromStr;
 
+use fake_vice_bin::parse::find_top_level;
+use fake_vice_bin::parse::parse_number;
+use fake_vice_bin::to_petscii;
+use fake_vice_bin::CheckpointOperation;
+use fake_vice_bin::ConditionExpr;
+use fake_vice_bin::ErrorCode;
 use fake_vice_bin::FakeViceBin;
+use fake_vice_bin::JoystickState;
+use fake_vice_bin::ReconnectPolicy;
+use fake_vice_bin::Region;
+use fake_vice_bin::ScreenDiff;
+use fake_vice_bin::Screenshot;
+use fake_vice_bin::ViceError;
+
+// step(), next(), and finish() give up after this
+const STEP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+const SCREENSHOT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+// single memory gets for print_screen() and expect_text()
+const SCREEN_TEXT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+const EXPECT_TEXT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+// save_state() and load_state() wait for VICE to finish
+const STATE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[derive(Debug, Default)]
 enum Condition {
 	#[default]
 	None,
 	IsResetPending,
+	IsMemoryGetPending,
+	WasReconnected,
+	HasError,
+	LastError {
+		code: ErrorCode,
+	},
 	And {
 		left:  Box<Condition>,
 		right: Box<Condition>,
@@ -26,6 +54,18 @@ enum Condition {
 impl From<&str> for Condition {
 	fn from(s: &str) -> Self {
 		let s = s.trim();
+		if let Some(i) = find_top_level(s, "||") {
+			return Condition::Or {
+				left:  Box::new(s[..i].into()),
+				right: Box::new(s[i + 2..].into()),
+			};
+		}
+		if let Some(i) = find_top_level(s, "&&") {
+			return Condition::And {
+				left:  Box::new(s[..i].into()),
+				right: Box::new(s[i + 2..].into()),
+			};
+		}
 		if let Some(s) = s.strip_prefix("is_reset_pending") {
 			let s = s.trim();
 			if let Some(s) = s.strip_prefix("(") {
@@ -35,6 +75,40 @@ impl From<&str> for Condition {
 				}
 			}
 		}
+		if let Some(s) = s.strip_prefix("is_memory_get_pending") {
+			let s = s.trim();
+			if let Some(s) = s.strip_prefix("(") {
+				let s = s.trim();
+				if let Some(_s) = s.strip_prefix(")") {
+					return Condition::IsMemoryGetPending;
+				}
+			}
+		}
+		if let Some(s) = s.strip_prefix("was_reconnected") {
+			let s = s.trim();
+			if let Some(s) = s.strip_prefix("(") {
+				let s = s.trim();
+				if let Some(_s) = s.strip_prefix(")") {
+					return Condition::WasReconnected;
+				}
+			}
+		}
+		if let Some(s) = s.strip_prefix("has_error") {
+			let s = s.trim();
+			if let Some(s) = s.strip_prefix("(") {
+				let s = s.trim();
+				if let Some(_s) = s.strip_prefix(")") {
+					return Condition::HasError;
+				}
+			}
+		}
+		if let Some(s) = s.strip_prefix("last_error(") {
+			if let Some(code) = s.strip_suffix(")") {
+				if let Ok(code) = code.parse() {
+					return Condition::LastError { code };
+				}
+			}
+		}
 
 		Condition::Invalid {
 			condition: s.to_owned(),
@@ -47,7 +121,14 @@ enum Command {
 	#[default]
 	None,
 	BlockDuringReset,
-	Connect,
+	Connect {
+		endpoint: Option<(String, u16)>,
+	},
+	Disconnect,
+	SetReconnect {
+		max_attempts: u32,
+		seconds:      f32,
+	},
 	Update,
 	SendLoad {
 		filename:  String,
@@ -59,11 +140,78 @@ enum Command {
 	SendAdvanceInstructions {
 		count: u16,
 	},
+	Step {
+		count:                 u16,
+		step_over_subroutines: bool,
+	},
+	Finish,
+	Screenshot {
+		filename: String,
+	},
+	AssertScreen {
+		golden:    String,
+		tolerance: u8,
+		masks:     Vec<Region>,
+	},
+	SaveState {
+		filename: String,
+	},
+	LoadState {
+		filename: String,
+	},
+	ScreenFromVic {
+		enabled: bool,
+	},
+	PrintScreen,
+	ExpectText {
+		text:    String,
+		seconds: f32,
+	},
+	Type {
+		text: String,
+	},
+	Joystick {
+		port:  u8,
+		state: JoystickState,
+	},
+	Userport {
+		value: u16,
+	},
+	SendCheckpointSet {
+		start:     u16,
+		end:       u16,
+		operation: CheckpointOperation,
+		condition: Option<ConditionExpr>,
+	},
+	SendConditionSet {
+		checkpoint: u32,
+		condition:  ConditionExpr,
+	},
+	SetRegister {
+		name:  String,
+		value: u16,
+	},
+	SendMemoryGet {
+		start: u16,
+		end:   u16,
+	},
+	SendMemorySet {
+		start: u16,
+		bytes: Vec<u8>,
+	},
+	PrintMemory,
 	SendReset,
 	SendExit,
 	Sleep {
 		seconds: f32,
 	},
+	Wait {
+		seconds: f32,
+	},
+	ExpectError {
+		code: ErrorCode,
+	},
+	ClearError,
 	Jump {
 		target: String,
 	},
@@ -87,18 +235,56 @@ impl Script {
 		Default::default()
 	}
 
-	fn eval_condition(fvb: &mut FakeViceBin, condition: &Condition) -> anyhow::Result<bool> {
+	// reconnects_seen is updated, so was_reconnected() only fires once per reconnect
+	fn eval_condition(
+		fvb: &mut FakeViceBin,
+		condition: &Condition,
+		reconnects_seen: &mut u32,
+		last_error: Option<&ViceError>,
+	) -> anyhow::Result<bool> {
 		match condition {
-			Condition::IsResetPending => {
-				return Ok(fvb.is_reset_pending());
+			Condition::IsResetPending => Ok(fvb.is_reset_pending()),
+			Condition::IsMemoryGetPending => Ok(fvb.is_memory_get_pending()),
+			Condition::WasReconnected => {
+				let reconnected = fvb.reconnect_count() != *reconnects_seen;
+				*reconnects_seen = fvb.reconnect_count();
+				Ok(reconnected)
+			},
+			Condition::HasError => Ok(last_error.is_some()),
+			Condition::LastError { code } => Ok(last_error.map(|e| e.code()) == Some(*code)),
+			Condition::And { left, right } => {
+				Ok(
+					Self::eval_condition(fvb, left, reconnects_seen, last_error)?
+						&& Self::eval_condition(fvb, right, reconnects_seen, last_error)?,
+				)
+			},
+			Condition::Or { left, right } => {
+				Ok(
+					Self::eval_condition(fvb, left, reconnects_seen, last_error)?
+						|| Self::eval_condition(fvb, right, reconnects_seen, last_error)?,
+				)
+			},
+			Condition::Invalid { condition } => {
+				anyhow::bail!("Invalid condition >{}<", condition);
 			},
 			c => {
 				anyhow::bail!("Condition {:?} not implemented", c);
 			},
 		}
 	}
-	fn add_connect(&mut self) {
-		let c = Command::Connect;
+	fn add_connect(&mut self, endpoint: Option<(String, u16)>) {
+		let c = Command::Connect { endpoint };
+		self.commands.push(c);
+	}
+	fn add_disconnect(&mut self) {
+		let c = Command::Disconnect;
+		self.commands.push(c);
+	}
+	fn add_set_reconnect(&mut self, max_attempts: u32, seconds: f32) {
+		let c = Command::SetReconnect {
+			max_attempts,
+			seconds,
+		};
 		self.commands.push(c);
 	}
 	fn add_block_during_reset(&mut self) {
@@ -137,10 +323,118 @@ impl Script {
 		let c = Command::SendAdvanceInstructions { count };
 		self.commands.push(c);
 	}
+	fn add_type(&mut self, text: String) {
+		let c = Command::Type { text };
+		self.commands.push(c);
+	}
+	fn add_joystick(&mut self, port: u8, state: JoystickState) {
+		let c = Command::Joystick { port, state };
+		self.commands.push(c);
+	}
+	fn add_userport(&mut self, value: u16) {
+		let c = Command::Userport { value };
+		self.commands.push(c);
+	}
+	fn add_step(&mut self, count: u16, step_over_subroutines: bool) {
+		let c = Command::Step {
+			count,
+			step_over_subroutines,
+		};
+		self.commands.push(c);
+	}
+	fn add_finish(&mut self) {
+		let c = Command::Finish;
+		self.commands.push(c);
+	}
+	fn add_screenshot(&mut self, filename: String) {
+		let c = Command::Screenshot { filename };
+		self.commands.push(c);
+	}
+	fn add_assert_screen(&mut self, golden: String, tolerance: u8, masks: Vec<Region>) {
+		let c = Command::AssertScreen {
+			golden,
+			tolerance,
+			masks,
+		};
+		self.commands.push(c);
+	}
+	fn add_save_state(&mut self, filename: String) {
+		let c = Command::SaveState { filename };
+		self.commands.push(c);
+	}
+	fn add_load_state(&mut self, filename: String) {
+		let c = Command::LoadState { filename };
+		self.commands.push(c);
+	}
+	fn add_screen_from_vic(&mut self, enabled: bool) {
+		let c = Command::ScreenFromVic { enabled };
+		self.commands.push(c);
+	}
+	fn add_print_screen(&mut self) {
+		let c = Command::PrintScreen;
+		self.commands.push(c);
+	}
+	fn add_expect_text(&mut self, text: String, seconds: f32) {
+		let c = Command::ExpectText { text, seconds };
+		self.commands.push(c);
+	}
+	fn add_send_checkpoint_set(
+		&mut self,
+		start: u16,
+		end: u16,
+		operation: CheckpointOperation,
+		condition: Option<ConditionExpr>,
+	) {
+		let c = Command::SendCheckpointSet {
+			start,
+			end,
+			operation,
+			condition,
+		};
+		self.commands.push(c);
+	}
+	fn add_send_condition_set(&mut self, checkpoint: u32, condition: ConditionExpr) {
+		let c = Command::SendConditionSet {
+			checkpoint,
+			condition,
+		};
+		self.commands.push(c);
+	}
+	fn add_set_register(&mut self, name: &str, value: u16) {
+		let c = Command::SetRegister {
+			name: name.to_owned(),
+			value,
+		};
+		self.commands.push(c);
+	}
+	fn add_send_memory_get(&mut self, start: u16, end: u16) {
+		let c = Command::SendMemoryGet { start, end };
+		self.commands.push(c);
+	}
+	fn add_send_memory_set(&mut self, start: u16, bytes: Vec<u8>) {
+		let c = Command::SendMemorySet { start, bytes };
+		self.commands.push(c);
+	}
+	fn add_print_memory(&mut self) {
+		let c = Command::PrintMemory;
+		self.commands.push(c);
+	}
 	fn add_sleep(&mut self, seconds: f32) {
 		let c = Command::Sleep { seconds };
 		self.commands.push(c);
 	}
+	fn add_wait(&mut self, seconds: f32) {
+		let c = Command::Wait { seconds };
+		self.commands.push(c);
+	}
+	fn add_expect_error(&mut self, code: ErrorCode) {
+		let c = Command::ExpectError { code };
+		self.commands.push(c);
+	}
+	fn add_clear_error(&mut self) {
+		let c = Command::ClearError;
+		self.commands.push(c);
+	}
 	fn add_if(&mut self, condition: &str) {
 		let c = Command::If {
 			condition: condition.into(),
@@ -164,11 +458,118 @@ impl Script {
 		}
 		self.commands.push(c);
 	}
+	// "quoted", with \", \\, and \n
+	fn parse_string(s: &str) -> anyhow::Result<String> {
+		let s = s.trim();
+		let Some(inner) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+			anyhow::bail!("String >{}< must be quoted", s);
+		};
+		let mut string = String::with_capacity(inner.len());
+		let mut chars = inner.chars();
+		while let Some(c) = chars.next() {
+			match c {
+				'\\' => match chars.next() {
+					Some('n') => string.push('\n'),
+					Some(c @ ('"' | '\\')) => string.push(c),
+					Some(c) => anyhow::bail!("Unknown escape \\{} in >{}<", c, s),
+					None => anyhow::bail!("Trailing \\ in >{}<", s),
+				},
+				'"' => anyhow::bail!("Unescaped \" in >{}<", s),
+				c => string.push(c),
+			}
+		}
+		Ok(string)
+	}
+	// quoted string at the start of `s`, and whatever follows it
+	fn split_string(s: &str) -> anyhow::Result<(String, &str)> {
+		let s = s.trim_start();
+		let Some(i) = Self::closing_quote(s) else {
+			anyhow::bail!("Missing closing \" on >{}<", s);
+		};
+		Ok((Self::parse_string(&s[..=i])?, &s[i + 1..]))
+	}
+	// position of the quote ending the string `s` starts with
+	fn closing_quote(s: &str) -> Option<usize> {
+		let mut escaped = false;
+		for (i, c) in s.char_indices().skip(1) {
+			match c {
+				_ if escaped => escaped = false,
+				'\\' => escaped = true,
+				'"' => return Some(i),
+				_ => {},
+			}
+		}
+		None
+	}
+	// everything before a // outside of strings
+	fn strip_comment(line: &str) -> &str {
+		let mut i = 0;
+		while let Some(c) = line[i..].chars().next() {
+			if line[i..].starts_with("//") {
+				return &line[..i];
+			}
+			if c == '"' {
+				match Self::closing_quote(&line[i..]) {
+					Some(end) => i += end,
+					None => return line, // reported when parsing the command
+				}
+			}
+			i += c.len_utf8();
+		}
+		line
+	}
+	// parameters separated by commas outside of parentheses
+	fn split_params(s: &str) -> Vec<&str> {
+		let mut params = Vec::new();
+		let mut s = s;
+		while let Some(i) = find_top_level(s, ",") {
+			params.push(s[..i].trim());
+			s = &s[i + 1..];
+		}
+		params.push(s.trim());
+		params
+	}
+	// mask(x, y, width, height)
+	fn parse_region(s: &str) -> anyhow::Result<Region> {
+		let Some(r) = s.strip_prefix("mask(").and_then(|r| r.strip_suffix(")")) else {
+			anyhow::bail!("Expected mask(x, y, width, height), got >{}<", s);
+		};
+		let r = r
+			.split(",")
+			.map(|v| parse_number(v).map(|v| v as u32))
+			.collect::<anyhow::Result<Vec<u32>>>()?;
+		if r.len() != 4 {
+			anyhow::bail!("Expected mask(x, y, width, height), got >{}<", s);
+		}
+		Ok(Region::new(r[0], r[1], r[2], r[3]))
+	}
+	fn parse_u8(s: &str) -> anyhow::Result<u8> {
+		let v = parse_number(s)?;
+		u8::try_from(v).map_err(|_| anyhow::anyhow!("Number >{}< does not fit in a byte", s.trim()))
+	}
+	// exec, load, store, or combinations like load|store
+	fn parse_checkpoint_operation(s: &str) -> anyhow::Result<CheckpointOperation> {
+		let mut operation = CheckpointOperation::default();
+		for o in s.split("|") {
+			operation = operation
+				| match o.trim() {
+					"exec" => CheckpointOperation::EXEC,
+					"load" => CheckpointOperation::LOAD,
+					"store" => CheckpointOperation::STORE,
+					o => anyhow::bail!("Invalid checkpoint operation >{}<", o),
+				};
+		}
+		Ok(operation)
+	}
 	fn add_from_str(&mut self, s: &str, line_no: usize) -> anyhow::Result<()> {
 		// :TODO: some regexes might be better, or one of the parsing packages
-		if let Some((label, _)) = s.split_once(":") {
-			println!("Label >{}<", &label);
-			self.add_label(&label);
+		// labels are plain identifiers, so `connect("::1", 6502);` is not one
+		let label = s.split_once(":").filter(|(label, _)| {
+			!label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+		});
+		if let Some((label, _)) = label {
+			log::debug!("Label >{}<", &label);
+			self.add_label(label);
 		} else if let Some(an_if) = s.strip_prefix("if") {
 			let an_if = an_if.trim();
 			if let Some(an_if) = an_if.strip_prefix("(") {
@@ -187,23 +588,104 @@ impl Script {
 		} else if let Some(cmd) = s.strip_suffix(";") {
 			if let Some(jump) = cmd.strip_prefix("jump(") {
 				if let Some(jump) = jump.strip_suffix(")") {
-					self.add_jump(&jump);
+					self.add_jump(jump);
 				} else {
 					anyhow::bail!("Missing closing ) on jump in line {}", line_no);
 				}
 			} else if let Some(sleep) = cmd.strip_prefix("sleep(") {
 				if let Some(sleep) = sleep.strip_suffix(")") {
-					let s = f32::from_str(sleep).expect("NaN");
+					let s = f32::from_str(sleep.trim()).map_err(|e| {
+						anyhow::anyhow!("Invalid seconds >{}<: {} in line {}", sleep, e, line_no)
+					})?;
 					self.add_sleep(s);
 				} else {
 					anyhow::bail!("Missing closing ) on sleep in line {}", line_no);
 				}
+			} else if let Some(wait) = cmd.strip_prefix("wait(") {
+				if let Some(wait) = wait.strip_suffix(")") {
+					let s = f32::from_str(wait.trim()).map_err(|e| {
+						anyhow::anyhow!("Invalid seconds >{}<: {} in line {}", wait, e, line_no)
+					})?;
+					self.add_wait(s);
+				} else {
+					anyhow::bail!("Missing closing ) on wait in line {}", line_no);
+				}
+			} else if let Some(expect_error) = cmd.strip_prefix("expect_error(") {
+				if let Some(code) = expect_error.strip_suffix(")") {
+					let code = code
+						.parse()
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_expect_error(code);
+				} else {
+					anyhow::bail!("Missing closing ) on expect_error in line {}", line_no);
+				}
+			} else if let Some(clear_error) = cmd.strip_prefix("clear_error(") {
+				if clear_error.strip_suffix(")").is_some() {
+					self.add_clear_error();
+				} else {
+					anyhow::bail!("Missing closing ) on clear_error in line {}", line_no);
+				}
 			} else if let Some(connect) = cmd.strip_prefix("connect(") {
-				if let Some(_) = connect.strip_suffix(")") {
-					self.add_connect();
+				if let Some(c) = connect.strip_suffix(")") {
+					if c.trim().is_empty() {
+						self.add_connect(None);
+					} else if let Some((host, port)) = c.rsplit_once(",") {
+						let host = host.trim();
+						let Some(host) = host.strip_prefix('"').and_then(|h| h.strip_suffix('"'))
+						else {
+							anyhow::bail!("Host >{}< must be quoted in line {}", host, line_no);
+						};
+						let port = port.trim().parse::<u16>().map_err(|e| {
+							anyhow::anyhow!(
+								"Invalid port >{}< in line {}: {}",
+								port.trim(),
+								line_no,
+								e
+							)
+						})?;
+						self.add_connect(Some((host.to_owned(), port)));
+					} else {
+						anyhow::bail!("Wrong number of parameters for connect in line {}", line_no);
+					}
 				} else {
 					anyhow::bail!("Missing closing ) on connect in line {}", line_no);
 				}
+			} else if let Some(disconnect) = cmd.strip_prefix("disconnect(") {
+				if disconnect.strip_suffix(")").is_some() {
+					self.add_disconnect();
+				} else {
+					anyhow::bail!("Missing closing ) on disconnect in line {}", line_no);
+				}
+			} else if let Some(reconnect) = cmd.strip_prefix("reconnect(") {
+				if let Some(r) = reconnect.strip_suffix(")") {
+					let params = r.split(",").collect::<Vec<&str>>();
+					if params.len() == 2 {
+						let max_attempts = params[0].trim().parse::<u32>().map_err(|e| {
+							anyhow::anyhow!(
+								"Invalid attempts >{}< in line {}: {}",
+								params[0],
+								line_no,
+								e
+							)
+						})?;
+						let seconds = params[1].trim().parse::<f32>().map_err(|e| {
+							anyhow::anyhow!(
+								"Invalid delay >{}< in line {}: {}",
+								params[1],
+								line_no,
+								e
+							)
+						})?;
+						self.add_set_reconnect(max_attempts, seconds);
+					} else {
+						anyhow::bail!(
+							"Wrong number of parameters for reconnect in line {}",
+							line_no
+						);
+					}
+				} else {
+					anyhow::bail!("Missing closing ) on reconnect in line {}", line_no);
+				}
 			} else if let Some(load) = cmd.strip_prefix("send_load(") {
 				if let Some(l) = load.strip_suffix(")") {
 					let params = l.split(",").collect::<Vec<&str>>();
@@ -228,7 +710,7 @@ impl Script {
 					anyhow::bail!("Missing closing ) on send_load in line {}", line_no);
 				}
 			} else if let Some(block_during_reset) = cmd.strip_prefix("block_during_reset(") {
-				if let Some(_) = block_during_reset.strip_suffix(")") {
+				if block_during_reset.strip_suffix(")").is_some() {
 					self.add_block_during_reset();
 				} else {
 					anyhow::bail!(
@@ -237,26 +719,27 @@ impl Script {
 					);
 				}
 			} else if let Some(update) = cmd.strip_prefix("update(") {
-				if let Some(_) = update.strip_suffix(")") {
+				if update.strip_suffix(")").is_some() {
 					self.add_update();
 				} else {
 					anyhow::bail!("Missing closing ) on update in line {}", line_no);
 				}
 			} else if let Some(send_reset) = cmd.strip_prefix("send_reset(") {
-				if let Some(_) = send_reset.strip_suffix(")") {
+				if send_reset.strip_suffix(")").is_some() {
 					self.add_send_reset();
 				} else {
 					anyhow::bail!("Missing closing ) on send_reset in line {}", line_no);
 				}
 			} else if let Some(send_exit) = cmd.strip_prefix("send_exit(") {
-				if let Some(_) = send_exit.strip_suffix(")") {
+				if send_exit.strip_suffix(")").is_some() {
 					self.add_send_exit();
 				} else {
 					anyhow::bail!("Missing closing ) on send_exit in line {}", line_no);
 				}
 			} else if let Some(r) = cmd.strip_prefix("send_registers_available(") {
 				if let Some(m) = r.strip_suffix(")") {
-					let m = u8::from_str_radix(m, 10).expect("NaN");
+					let m = Self::parse_u8(m)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
 					self.add_send_registers_available(m);
 				} else {
 					anyhow::bail!(
@@ -266,7 +749,8 @@ impl Script {
 				}
 			} else if let Some(i) = cmd.strip_prefix("send_advance_instructions(") {
 				if let Some(c) = i.strip_suffix(")") {
-					let c = u16::from_str_radix(c, 10).expect("NaN");
+					let c = parse_number(c)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
 					self.add_send_advance_instructions(c);
 				} else {
 					anyhow::bail!(
@@ -274,6 +758,279 @@ impl Script {
 						line_no
 					);
 				}
+			} else if let Some(t) = cmd.strip_prefix("type(") {
+				if let Some(t) = t.strip_suffix(")") {
+					let text = Self::parse_string(t)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					// fail early on text that can't be typed
+					to_petscii(&text).map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_type(text);
+				} else {
+					anyhow::bail!("Missing closing ) on type in line {}", line_no);
+				}
+			} else if let Some(s) = cmd.strip_prefix("step(") {
+				if let Some(count) = s.strip_suffix(")") {
+					let count = parse_number(count)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_step(count, false);
+				} else {
+					anyhow::bail!("Missing closing ) on step in line {}", line_no);
+				}
+			} else if let Some(n) = cmd.strip_prefix("next(") {
+				if let Some(count) = n.strip_suffix(")") {
+					let count = parse_number(count)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_step(count, true);
+				} else {
+					anyhow::bail!("Missing closing ) on next in line {}", line_no);
+				}
+			} else if let Some(finish) = cmd.strip_prefix("finish(") {
+				match finish.strip_suffix(")") {
+					Some(params) if params.trim().is_empty() => self.add_finish(),
+					Some(params) => anyhow::bail!(
+						"finish takes no parameters, got >{}< in line {}",
+						params,
+						line_no
+					),
+					None => anyhow::bail!("Missing closing ) on finish in line {}", line_no),
+				}
+			} else if let Some(s) = cmd.strip_prefix("screenshot(") {
+				if let Some(filename) = s.strip_suffix(")") {
+					let filename = Self::parse_string(filename)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_screenshot(filename);
+				} else {
+					anyhow::bail!("Missing closing ) on screenshot in line {}", line_no);
+				}
+			} else if let Some(a) = cmd.strip_prefix("assert_screen(") {
+				if let Some(a) = a.strip_suffix(")") {
+					let (golden, rest) = Self::split_string(a)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					let mut tolerance = 0;
+					let mut masks = Vec::new();
+					if let Some(rest) = rest.trim().strip_prefix(",") {
+						let params = Self::split_params(rest);
+						tolerance = Self::parse_u8(params[0])
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						for p in &params[1..] {
+							let mask = Self::parse_region(p)
+								.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+							masks.push(mask);
+						}
+					} else if !rest.trim().is_empty() {
+						anyhow::bail!("Unexpected >{}< on assert_screen in line {}", rest, line_no);
+					}
+					self.add_assert_screen(golden, tolerance, masks);
+				} else {
+					anyhow::bail!("Missing closing ) on assert_screen in line {}", line_no);
+				}
+			} else if let Some(s) = cmd.strip_prefix("save_state(") {
+				if let Some(filename) = s.strip_suffix(")") {
+					let filename = Self::parse_string(filename)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_save_state(filename);
+				} else {
+					anyhow::bail!("Missing closing ) on save_state in line {}", line_no);
+				}
+			} else if let Some(l) = cmd.strip_prefix("load_state(") {
+				if let Some(filename) = l.strip_suffix(")") {
+					let filename = Self::parse_string(filename)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_load_state(filename);
+				} else {
+					anyhow::bail!("Missing closing ) on load_state in line {}", line_no);
+				}
+			} else if let Some(s) = cmd.strip_prefix("screen_from_vic(") {
+				if let Some(enabled) = s.strip_suffix(")") {
+					let enabled = match enabled.trim() {
+						"true" => true,
+						"false" => false,
+						e => {
+							anyhow::bail!("Expected true or false, got >{}< in line {}", e, line_no)
+						},
+					};
+					self.add_screen_from_vic(enabled);
+				} else {
+					anyhow::bail!("Missing closing ) on screen_from_vic in line {}", line_no);
+				}
+			} else if let Some(print_screen) = cmd.strip_prefix("print_screen(") {
+				if print_screen.strip_suffix(")").is_some() {
+					self.add_print_screen();
+				} else {
+					anyhow::bail!("Missing closing ) on print_screen in line {}", line_no);
+				}
+			} else if let Some(e) = cmd.strip_prefix("expect_text(") {
+				if let Some(e) = e.strip_suffix(")") {
+					let (text, rest) = Self::split_string(e)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					let Some(seconds) = rest.trim().strip_prefix(",") else {
+						anyhow::bail!(
+							"Wrong number of parameters for expect_text in line {}",
+							line_no
+						);
+					};
+					let seconds = seconds.trim().parse::<f32>().map_err(|e| {
+						anyhow::anyhow!(
+							"Invalid timeout >{}< in line {}: {}",
+							seconds.trim(),
+							line_no,
+							e
+						)
+					})?;
+					self.add_expect_text(text, seconds);
+				} else {
+					anyhow::bail!("Missing closing ) on expect_text in line {}", line_no);
+				}
+			} else if let Some(j) = cmd.strip_prefix("joystick(") {
+				if let Some(j) = j.strip_suffix(")") {
+					if let Some((port, state)) = j.split_once(",") {
+						let port = Self::parse_u8(port)
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						if !(1..=2).contains(&port) {
+							anyhow::bail!("Invalid joystick port {} in line {}", port, line_no);
+						}
+						let state = Self::parse_string(state)
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?
+							.parse()
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						self.add_joystick(port, state);
+					} else {
+						anyhow::bail!(
+							"Wrong number of parameters for joystick in line {}",
+							line_no
+						);
+					}
+				} else {
+					anyhow::bail!("Missing closing ) on joystick in line {}", line_no);
+				}
+			} else if let Some(u) = cmd.strip_prefix("userport(") {
+				if let Some(u) = u.strip_suffix(")") {
+					let value = parse_number(u)
+						.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+					self.add_userport(value);
+				} else {
+					anyhow::bail!("Missing closing ) on userport in line {}", line_no);
+				}
+			} else if let Some(c) = cmd.strip_prefix("send_checkpoint_set(") {
+				if let Some(c) = c.strip_suffix(")") {
+					let params = c.splitn(4, ",").collect::<Vec<&str>>();
+					if params.len() >= 3 {
+						let start = parse_number(params[0])
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						let end = parse_number(params[1])
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						let operation = Self::parse_checkpoint_operation(params[2])
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						let condition = match params.get(3) {
+							Some(condition) => Some(
+								condition
+									.parse::<ConditionExpr>()
+									.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?,
+							),
+							None => None,
+						};
+						self.add_send_checkpoint_set(start, end, operation, condition);
+					} else {
+						anyhow::bail!(
+							"Wrong number of parameters for send_checkpoint_set in line {}",
+							line_no
+						);
+					}
+				} else {
+					anyhow::bail!(
+						"Missing closing ) on send_checkpoint_set in line {}",
+						line_no
+					);
+				}
+			} else if let Some(c) = cmd.strip_prefix("send_condition_set(") {
+				if let Some(c) = c.strip_suffix(")") {
+					if let Some((checkpoint, condition)) = c.split_once(",") {
+						let checkpoint = checkpoint.trim().parse::<u32>().map_err(|e| {
+							anyhow::anyhow!(
+								"Invalid checkpoint >{}<: {} in line {}",
+								checkpoint.trim(),
+								e,
+								line_no
+							)
+						})?;
+						let condition = condition
+							.parse::<ConditionExpr>()
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						self.add_send_condition_set(checkpoint, condition);
+					} else {
+						anyhow::bail!(
+							"Wrong number of parameters for send_condition_set in line {}",
+							line_no
+						);
+					}
+				} else {
+					anyhow::bail!(
+						"Missing closing ) on send_condition_set in line {}",
+						line_no
+					);
+				}
+			} else if let Some(r) = cmd.strip_prefix("set_register(") {
+				if let Some(r) = r.strip_suffix(")") {
+					if let Some((name, value)) = r.split_once(",") {
+						let value = parse_number(value)
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						self.add_set_register(name.trim(), value);
+					} else {
+						anyhow::bail!(
+							"Wrong number of parameters for set_register in line {}",
+							line_no
+						);
+					}
+				} else {
+					anyhow::bail!("Missing closing ) on set_register in line {}", line_no);
+				}
+			} else if let Some(m) = cmd.strip_prefix("send_memory_get(") {
+				if let Some(m) = m.strip_suffix(")") {
+					let params = m.split(",").collect::<Vec<&str>>();
+					if params.len() == 2 {
+						let start = parse_number(params[0])
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						let end = parse_number(params[1])
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						self.add_send_memory_get(start, end);
+					} else {
+						anyhow::bail!(
+							"Wrong number of parameters for send_memory_get in line {}",
+							line_no
+						);
+					}
+				} else {
+					anyhow::bail!("Missing closing ) on send_memory_get in line {}", line_no);
+				}
+			} else if let Some(m) = cmd.strip_prefix("send_memory_set(") {
+				if let Some(m) = m.strip_suffix(")") {
+					let params = m.split(",").collect::<Vec<&str>>();
+					if params.len() >= 2 {
+						let start = parse_number(params[0])
+							.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?;
+						let mut bytes = Vec::new();
+						for p in params[1..].iter() {
+							bytes.push(
+								Self::parse_u8(p)
+									.map_err(|e| anyhow::anyhow!("{} in line {}", e, line_no))?,
+							);
+						}
+						self.add_send_memory_set(start, bytes);
+					} else {
+						anyhow::bail!(
+							"Wrong number of parameters for send_memory_set in line {}",
+							line_no
+						);
+					}
+				} else {
+					anyhow::bail!("Missing closing ) on send_memory_set in line {}", line_no);
+				}
+			} else if let Some(print_memory) = cmd.strip_prefix("print_memory(") {
+				if print_memory.strip_suffix(")").is_some() {
+					self.add_print_memory();
+				} else {
+					anyhow::bail!("Missing closing ) on print_memory in line {}", line_no);
+				}
 			} else {
 				anyhow::bail!("Unkown command >{}< in line {}", &s, line_no);
 			}
@@ -287,21 +1044,84 @@ impl Script {
 		let lines = io::BufReader::new(file).lines();
 		for (line_no, line) in lines.enumerate() {
 			if let Ok(line) = line {
-				let line = line.split_once("//").unwrap_or((&line, "")).0;
+				let line = Self::strip_comment(&line);
 				let line = line.trim();
 				if line.is_empty() {
 					continue;
 				}
-				println!("{:?}", &line);
+				log::debug!("{:?}", &line);
 				self.add_from_str(line, line_no)?;
 			}
 		}
 		Ok(())
 	}
 
-	pub fn run(&mut self) -> anyhow::Result<()> {
-		let mut fvb = FakeViceBin::new("127.0.0.1", 6502);
+	// errors reported by VICE are kept for has_error() and expect_error(), others end the script
+	fn vice_error(result: anyhow::Result<()>) -> anyhow::Result<Option<ViceError>> {
+		match result {
+			Ok(()) => Ok(None),
+			Err(e) => {
+				let e = e.downcast::<ViceError>()?;
+				log::debug!("{}", e);
+				Ok(Some(e))
+			},
+		}
+	}
+
+	// on failure the current screen, and the diff if sizes match, are written next to the golden image
+	fn assert_screen(
+		actual: &Screenshot,
+		golden: &str,
+		tolerance: u8,
+		masks: &[Region],
+	) -> anyhow::Result<()> {
+		let golden_path = Path::new(golden);
+		let extension = golden_path
+			.extension()
+			.and_then(|e| e.to_str())
+			.unwrap_or("png");
+		let actual_path = golden_path.with_extension(format!("actual.{}", extension));
+		let diff = match Screenshot::load(golden_path)
+			.and_then(|g| ScreenDiff::compare(actual, &g, tolerance, masks))
+		{
+			Ok(diff) => diff,
+			Err(e) => {
+				actual.save(&actual_path)?;
+				anyhow::bail!("{}, current screen in {}", e, actual_path.display());
+			},
+		};
+		if !diff.matches() {
+			let diff_path = golden_path.with_extension(format!("diff.{}", extension));
+			actual.save(&actual_path)?;
+			diff.image().save(&diff_path)?;
+			anyhow::bail!(
+				"{} pixels differ by more than {}, diff in {}, current screen in {}",
+				diff.differing_pixels(),
+				tolerance,
+				diff_path.display(),
+				actual_path.display()
+			);
+		}
+		Ok(())
+	}
+
+	/// `host` and `port` are used by `connect();`, `connect("host", port);` switches to another emulator.
+	///
+	/// Errors VICE reports for requests are kept for `has_error()`, `last_error(name)`, and `expect_error(name);`,
+	/// the run fails if one is still set when the script ends.
+	///
+	/// Failed assertions are reported as they happen, the run fails at the end if there were any.
+	pub fn run(&mut self, host: &str, port: u16) -> anyhow::Result<()> {
+		let mut endpoint = (host.to_owned(), port);
+		let mut fvb = FakeViceBin::new(host, port)?;
+		// connections not currently in use, they stay open
+		let mut others: HashMap<(String, u16), FakeViceBin> = HashMap::new();
 		let mut pc = 0;
+		let mut reconnects_seen = 0;
+		let mut last_error: Option<ViceError> = None;
+		let mut assertions = 0;
+		let mut failed_assertions = 0;
+		let mut screen_from_vic = false;
 		loop {
 			if pc >= self.commands.len() {
 				break;
@@ -311,12 +1131,44 @@ impl Script {
 			//	fvb.update()?;
 			//}
 			let c = &self.commands[pc];
-			println!("{:?}", &c);
+			log::debug!("{:?}", &c);
 			match c {
-				Command::Connect => {
-					fvb.connect()?;
+				Command::Connect { endpoint: target } => {
+					if let Some(target) = target {
+						if *target != endpoint {
+							let next = match others.remove(target) {
+								Some(next) => next,
+								None => FakeViceBin::new(&target.0, target.1)?,
+							};
+							let previous = std::mem::replace(&mut fvb, next);
+							others
+								.insert(std::mem::replace(&mut endpoint, target.clone()), previous);
+						}
+					}
+					if !fvb.is_connected() {
+						fvb.connect()?;
+					}
 					// :TODO: block
 				},
+				Command::Disconnect => {
+					fvb.disconnect()?;
+				},
+				Command::SetReconnect {
+					max_attempts,
+					seconds,
+				} => {
+					let policy = if *max_attempts > 0 {
+						let delay = std::time::Duration::from_millis((*seconds * 1000.0) as u64);
+						Some(ReconnectPolicy::new(
+							*max_attempts,
+							delay,
+							ReconnectPolicy::default().max_delay().max(delay),
+						))
+					} else {
+						None
+					};
+					fvb.set_reconnect_policy(policy);
+				},
 				Command::Update => {
 					fvb.update()?;
 				},
@@ -337,7 +1189,133 @@ impl Script {
 					fvb.send_registers_available(*mem)?;
 				},
 				Command::SendAdvanceInstructions { count } => {
-					fvb.send_advance_instructions(*count)?;
+					fvb.send_advance_instructions(*count, false)?;
+				},
+				Command::Step {
+					count,
+					step_over_subroutines,
+				} => {
+					let pc = if *step_over_subroutines {
+						fvb.next(*count, STEP_TIMEOUT)?
+					} else {
+						fvb.step(*count, STEP_TIMEOUT)?
+					};
+					log::info!("Stopped at {:#06x}", pc);
+				},
+				Command::Screenshot { filename } => {
+					fvb.screenshot(SCREENSHOT_TIMEOUT)?.save(filename)?;
+					log::info!("Saved screenshot {}", filename);
+				},
+				Command::AssertScreen {
+					golden,
+					tolerance,
+					masks,
+				} => {
+					assertions += 1;
+					let actual = fvb.screenshot(SCREENSHOT_TIMEOUT)?;
+					match Self::assert_screen(&actual, golden, *tolerance, masks) {
+						Ok(()) => log::info!("assert_screen({}) passed", golden),
+						Err(e) => {
+							log::error!("assert_screen({}) failed: {}", golden, e);
+							failed_assertions += 1;
+						},
+					}
+				},
+				Command::SaveState { filename } => {
+					fvb.send_dump(filename, false, false)?;
+					match Self::vice_error(fvb.wait_for_pending(STATE_TIMEOUT))? {
+						Some(e) => last_error = Some(e),
+						None => log::info!("Saved state {}", filename),
+					}
+				},
+				Command::LoadState { filename } => {
+					fvb.send_undump(filename)?;
+					match Self::vice_error(fvb.wait_for_pending(STATE_TIMEOUT))? {
+						Some(e) => last_error = Some(e),
+						None => log::info!("Loaded state {}, PC {:#06x}", filename, fvb.pc()),
+					}
+				},
+				Command::ScreenFromVic { enabled } => {
+					screen_from_vic = *enabled;
+				},
+				Command::PrintScreen => {
+					println!("{}", fvb.screen_text(screen_from_vic, SCREEN_TEXT_TIMEOUT)?);
+				},
+				Command::ExpectText { text, seconds } => {
+					assertions += 1;
+					let timeout = std::time::Duration::from_millis((*seconds * 1000.0) as u64);
+					let deadline = std::time::Instant::now() + timeout;
+					loop {
+						let screen = fvb.screen_text(screen_from_vic, SCREEN_TEXT_TIMEOUT)?;
+						if screen.contains(text.as_str()) {
+							log::info!("expect_text({:?}) passed", text);
+							break;
+						}
+						if std::time::Instant::now() >= deadline {
+							log::error!(
+								"expect_text({:?}) failed after {}s, screen:\n{}",
+								text,
+								seconds,
+								screen
+							);
+							failed_assertions += 1;
+							break;
+						}
+						std::thread::sleep(EXPECT_TEXT_INTERVAL);
+					}
+				},
+				Command::Finish => {
+					let pc = fvb.finish(STEP_TIMEOUT)?;
+					log::info!("Stopped at {:#06x}", pc);
+				},
+				Command::Type { text } => {
+					fvb.send_keyboard_feed(text)?;
+				},
+				Command::Joystick { port, state } => {
+					fvb.send_joystick(*port, *state)?;
+				},
+				Command::Userport { value } => {
+					fvb.send_userport(*value)?;
+				},
+				Command::SendCheckpointSet {
+					start,
+					end,
+					operation,
+					condition,
+				} => match condition {
+					Some(condition) => {
+						fvb.send_checkpoint_set_with_condition(
+							*start, *end, *operation, condition,
+						)?;
+					},
+					None => {
+						fvb.send_checkpoint_set(*start, *end, true, true, *operation, false)?;
+					},
+				},
+				Command::SendConditionSet {
+					checkpoint,
+					condition,
+				} => {
+					fvb.send_condition_set(*checkpoint, condition)?;
+				},
+				Command::SetRegister { name, value } => {
+					fvb.set_register(name, *value)?;
+				},
+				Command::SendMemoryGet { start, end } => {
+					fvb.send_memory_get(*start, *end, 0, 0, false)?;
+				},
+				Command::SendMemorySet { start, bytes } => {
+					fvb.send_memory_set(*start, bytes, 0, 0, false)?;
+				},
+				Command::PrintMemory => {
+					let start = fvb.memory_start() as usize;
+					for (i, chunk) in fvb.memory().chunks(16).enumerate() {
+						print!("{:04x}:", start + i * 16);
+						for b in chunk.iter() {
+							print!(" {:02x}", b);
+						}
+						println!();
+					}
 				},
 				Command::SendReset => {
 					fvb.send_reset()?;
@@ -349,6 +1327,20 @@ impl Script {
 					let delay = std::time::Duration::from_millis((*seconds * 1000.0) as u64);
 					std::thread::sleep(delay);
 				},
+				Command::Wait { seconds } => {
+					let timeout = std::time::Duration::from_millis((*seconds * 1000.0) as u64);
+					if let Some(e) = Self::vice_error(fvb.wait_for_pending(timeout))? {
+						last_error = Some(e);
+					}
+				},
+				Command::ExpectError { code } => match last_error.take() {
+					Some(e) if e.code() == *code => {},
+					Some(e) => anyhow::bail!("Expected {}, got: {}", code, e),
+					None => anyhow::bail!("Expected {}, but no request failed", code),
+				},
+				Command::ClearError => {
+					last_error = None;
+				},
 				Command::Jump { target } => {
 					if let Some(t) = self.labels.get(target) {
 						pc = *t;
@@ -358,7 +1350,12 @@ impl Script {
 					}
 				},
 				Command::If { condition } => {
-					if Self::eval_condition(&mut fvb, &condition)? {
+					if Self::eval_condition(
+						&mut fvb,
+						condition,
+						&mut reconnects_seen,
+						last_error.as_ref(),
+					)? {
 						// nothing to do
 					} else {
 						// jump to else branch / end
@@ -384,7 +1381,7 @@ impl Script {
 								anyhow::bail!("Script ends within if {{"); // :TODO: should probably check this during parsing/verification
 							}
 						}
-						println!("Skipped {:?} to {}", &c, new_pc);
+						log::debug!("Skipped {:?} to {}", &c, new_pc);
 						pc = new_pc;
 					}
 				},
@@ -393,6 +1390,66 @@ impl Script {
 			}
 			pc += 1;
 		}
-		Ok(())
+		if failed_assertions > 0 {
+			anyhow::bail!("{} of {} assertions failed", failed_assertions, assertions);
+		}
+		match last_error {
+			Some(e) => Err(e.into()),
+			None => Ok(()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn strip_comment() {
+		assert_eq!(
+			Script::strip_comment(r#"type("http://x"); // comment"#),
+			r#"type("http://x"); "#
+		);
+		assert_eq!(
+			Script::strip_comment(r#"type("a\"//b"); // c"#),
+			r#"type("a\"//b"); "#
+		);
+		assert_eq!(Script::strip_comment("// all comment"), "");
+		assert_eq!(
+			Script::strip_comment(r#"type("open // "#),
+			r#"type("open // "#
+		);
+	}
+
+	#[test]
+	fn parse_string() {
+		assert_eq!(Script::parse_string(r#" "plain" "#).unwrap(), "plain");
+		assert_eq!(
+			Script::parse_string(r#""say \"hi\"\\\n""#).unwrap(),
+			"say \"hi\"\\\n"
+		);
+		assert!(Script::parse_string("unquoted").is_err());
+		assert!(Script::parse_string(r#""a"b""#).is_err());
+		assert!(Script::parse_string(r#""\x""#).is_err());
+	}
+
+	#[test]
+	fn split_string() {
+		let (s, rest) = Script::split_string(r#""a, b", 3"#).unwrap();
+		assert_eq!(s, "a, b");
+		assert_eq!(rest, ", 3");
+		let (s, rest) = Script::split_string(r#""x\"," , y"#).unwrap();
+		assert_eq!(s, "x\",");
+		assert_eq!(rest, " , y");
+		assert!(Script::split_string(r#""open, 3"#).is_err());
+	}
+
+	#[test]
+	fn split_params() {
+		assert_eq!(
+			Script::split_params("4, mask(0, 0, 8, 8), mask($10, 2, 3, 4)"),
+			vec!["4", "mask(0, 0, 8, 8)", "mask($10, 2, 3, 4)"]
+		);
+		assert_eq!(Script::split_params(" 1 "), vec!["1"]);
 	}
 }
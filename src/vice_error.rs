@@ -0,0 +1,169 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Error codes of the binary monitor, sent in the response header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+	ObjectMissing,
+	InvalidMemspace,
+	InvalidLength,
+	InvalidParameter,
+	UnsupportedApiVersion,
+	UnknownCommand,
+	GeneralFailure,
+	Other(u8),
+}
+
+impl ErrorCode {
+	// name as used in scripts, code
+	const ALL: [(&'static str, ErrorCode); 7] = [
+		("object_missing", ErrorCode::ObjectMissing),
+		("invalid_memspace", ErrorCode::InvalidMemspace),
+		("invalid_length", ErrorCode::InvalidLength),
+		("invalid_parameter", ErrorCode::InvalidParameter),
+		("unsupported_api_version", ErrorCode::UnsupportedApiVersion),
+		("unknown_command", ErrorCode::UnknownCommand),
+		("general_failure", ErrorCode::GeneralFailure),
+	];
+
+	/// `None` for 0x00, which means success.
+	pub fn from_code(code: u8) -> Option<Self> {
+		let e = match code {
+			0x00 => return None,
+			0x01 => ErrorCode::ObjectMissing,
+			0x02 => ErrorCode::InvalidMemspace,
+			0x80 => ErrorCode::InvalidLength,
+			0x81 => ErrorCode::InvalidParameter,
+			0x82 => ErrorCode::UnsupportedApiVersion,
+			0x83 => ErrorCode::UnknownCommand,
+			0x8f => ErrorCode::GeneralFailure,
+			c => ErrorCode::Other(c),
+		};
+		Some(e)
+	}
+	pub fn code(&self) -> u8 {
+		match self {
+			ErrorCode::ObjectMissing => 0x01,
+			ErrorCode::InvalidMemspace => 0x02,
+			ErrorCode::InvalidLength => 0x80,
+			ErrorCode::InvalidParameter => 0x81,
+			ErrorCode::UnsupportedApiVersion => 0x82,
+			ErrorCode::UnknownCommand => 0x83,
+			ErrorCode::GeneralFailure => 0x8f,
+			ErrorCode::Other(c) => *c,
+		}
+	}
+	/// True for codes VICE documents, others are still passed on as `Other`.
+	pub fn is_known(code: u8) -> bool {
+		!matches!(ErrorCode::from_code(code), Some(ErrorCode::Other(_)))
+	}
+	pub fn name(&self) -> Option<&'static str> {
+		ErrorCode::ALL
+			.iter()
+			.find(|(_, e)| e == self)
+			.map(|(name, _)| *name)
+	}
+}
+
+impl fmt::Display for ErrorCode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.name() {
+			Some(name) => write!(f, "{} ({:#04x})", name, self.code()),
+			None => write!(f, "unknown error ({:#04x})", self.code()),
+		}
+	}
+}
+
+impl FromStr for ErrorCode {
+	type Err = anyhow::Error;
+
+	/// Accepts the names from `name()`, or a number like `0x81`.
+	fn from_str(s: &str) -> anyhow::Result<Self> {
+		let s = s.trim();
+		if let Some((_, e)) = ErrorCode::ALL.iter().find(|(name, _)| *name == s) {
+			return Ok(*e);
+		}
+		let code = if let Some(hex) = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")) {
+			u8::from_str_radix(hex, 16)
+		} else {
+			s.parse()
+		};
+		match code.ok().and_then(ErrorCode::from_code) {
+			Some(e) => Ok(e),
+			None => anyhow::bail!("Invalid error code >{}<", s),
+		}
+	}
+}
+
+/// A request VICE answered with an error code.
+///
+/// Returned from the waits, reachable via `anyhow::Error::downcast_ref`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ViceError {
+	request_id: u32,
+	command:    u8,
+	code:       ErrorCode,
+}
+
+impl ViceError {
+	pub fn new(request_id: u32, command: u8, code: ErrorCode) -> Self {
+		Self {
+			request_id,
+			command,
+			code,
+		}
+	}
+
+	pub fn request_id(&self) -> u32 {
+		self.request_id
+	}
+	pub fn command(&self) -> u8 {
+		self.command
+	}
+	pub fn code(&self) -> ErrorCode {
+		self.code
+	}
+}
+
+impl fmt::Display for ViceError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"Request {:#010x} (command {:#04x}) failed with {}",
+			self.request_id, self.command, self.code
+		)
+	}
+}
+
+impl std::error::Error for ViceError {}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn code_round_trip() {
+		assert_eq!(ErrorCode::from_code(0x00), None);
+		for code in 0x01..=0xff {
+			assert_eq!(ErrorCode::from_code(code).unwrap().code(), code);
+		}
+		assert!(ErrorCode::is_known(0x8f));
+		assert!(!ErrorCode::is_known(0x84));
+	}
+
+	#[test]
+	fn from_str() {
+		assert_eq!(
+			"invalid_parameter".parse::<ErrorCode>().unwrap(),
+			ErrorCode::InvalidParameter
+		);
+		assert_eq!(
+			"0x83".parse::<ErrorCode>().unwrap(),
+			ErrorCode::UnknownCommand
+		);
+		assert_eq!("$84".parse::<ErrorCode>().unwrap(), ErrorCode::Other(0x84));
+		assert_eq!("1".parse::<ErrorCode>().unwrap(), ErrorCode::ObjectMissing);
+		assert!("0".parse::<ErrorCode>().is_err());
+		assert!("no_such_error".parse::<ErrorCode>().is_err());
+	}
+}
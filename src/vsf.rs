@@ -0,0 +1,297 @@
+use std::path::Path;
+
+use crate::reader::Reader;
+
+const MAGIC: &[u8] = b"VICE Snapshot File\x1a";
+const VERSION_MAGIC: &[u8] = b"VICE Version\x1a";
+const NAME_LEN: usize = 16;
+const MODULE_HEADER_LEN: usize = NAME_LEN + 2 + 4; // name, version, size
+const RAM_SIZE: usize = 0x10000;
+
+/// CPU state from the MAINCPU module.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CpuRegisters {
+	clock:  u64,
+	a:      u8,
+	x:      u8,
+	y:      u8,
+	sp:     u8,
+	pc:     u16,
+	status: u8,
+}
+
+impl CpuRegisters {
+	pub fn clock(&self) -> u64 {
+		self.clock
+	}
+	pub fn a(&self) -> u8 {
+		self.a
+	}
+	pub fn x(&self) -> u8 {
+		self.x
+	}
+	pub fn y(&self) -> u8 {
+		self.y
+	}
+	pub fn sp(&self) -> u8 {
+		self.sp
+	}
+	pub fn pc(&self) -> u16 {
+		self.pc
+	}
+	pub fn status(&self) -> u8 {
+		self.status
+	}
+}
+
+/// One chip or subsystem in a snapshot, e.g. MAINCPU, C64MEM, VIC-II, CIA1, or SID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VsfModule {
+	name:    String,
+	version: (u8, u8), // major, minor
+	data:    Vec<u8>,
+}
+
+impl VsfModule {
+	pub fn new(name: &str, version: (u8, u8), data: Vec<u8>) -> Self {
+		Self {
+			name: name.to_owned(),
+			version,
+			data,
+		}
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+	pub fn version(&self) -> (u8, u8) {
+		self.version
+	}
+	/// Module body, without the module header.
+	pub fn data(&self) -> &[u8] {
+		&self.data
+	}
+}
+
+/// VICE snapshot file (.vsf), as written by `FakeViceBin::send_dump`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vsf {
+	version:      (u8, u8), // major, minor
+	machine:      String,
+	vice_version: Option<(u8, u8, u8, u8)>, // only in files from newer VICE versions
+	modules:      Vec<VsfModule>,
+}
+
+impl Vsf {
+	pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+		let path = path.as_ref();
+		let bytes = std::fs::read(path)
+			.map_err(|e| anyhow::anyhow!("Error reading {}: {}", path.display(), e))?;
+		Self::parse(&bytes).map_err(|e| anyhow::anyhow!("Error parsing {}: {}", path.display(), e))
+	}
+
+	pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+		let mut r = Reader::new(bytes);
+		if r.bytes(MAGIC.len()).ok() != Some(MAGIC) {
+			anyhow::bail!("Not a VICE snapshot file");
+		}
+		let version = (r.u8()?, r.u8()?);
+		let machine = Self::name(r.bytes(NAME_LEN)?);
+
+		// the version block looks like a module name, so only skip it when it is there
+		let mut vice_version = None;
+		if bytes[bytes.len() - r.remaining()..].starts_with(VERSION_MAGIC) {
+			r.bytes(VERSION_MAGIC.len())?;
+			let v = r.bytes(4)?;
+			vice_version = Some((v[0], v[1], v[2], v[3]));
+			r.u32()?; // svn revision
+		}
+
+		let mut modules = Vec::new();
+		while r.remaining() > 0 {
+			let name = Self::name(r.bytes(NAME_LEN)?);
+			let version = (r.u8()?, r.u8()?);
+			let size = r.u32()? as usize;
+			if size < MODULE_HEADER_LEN {
+				anyhow::bail!("Module {} too small ({} bytes)", name, size);
+			}
+			let data = r.bytes(size - MODULE_HEADER_LEN)?.to_vec();
+			modules.push(VsfModule {
+				name,
+				version,
+				data,
+			});
+		}
+
+		Ok(Self {
+			version,
+			machine,
+			vice_version,
+			modules,
+		})
+	}
+
+	// zero padded
+	fn name(bytes: &[u8]) -> String {
+		let len = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+		String::from_utf8_lossy(&bytes[..len]).into_owned()
+	}
+
+	pub fn version(&self) -> (u8, u8) {
+		self.version
+	}
+	pub fn machine(&self) -> &str {
+		&self.machine
+	}
+	pub fn vice_version(&self) -> Option<(u8, u8, u8, u8)> {
+		self.vice_version
+	}
+	pub fn modules(&self) -> &[VsfModule] {
+		&self.modules
+	}
+	pub fn module(&self, name: &str) -> Option<&VsfModule> {
+		self.modules.iter().find(|m| m.name == name)
+	}
+
+	pub fn registers(&self) -> anyhow::Result<CpuRegisters> {
+		let Some(m) = self.module("MAINCPU") else {
+			anyhow::bail!("No MAINCPU module in snapshot");
+		};
+		let mut r = Reader::new(&m.data);
+		// :TODO: 64 bit clock from 1.2 on is what newer VICE versions write, check against more snapshots
+		let clock = if m.version < (1, 2) {
+			r.u32()? as u64
+		} else {
+			r.u32()? as u64 | (r.u32()? as u64) << 32
+		};
+		Ok(CpuRegisters {
+			clock,
+			a: r.u8()?,
+			x: r.u8()?,
+			y: r.u8()?,
+			sp: r.u8()?,
+			pc: r.u16()?,
+			status: r.u8()?,
+		})
+	}
+
+	/// The 64KB RAM image from the C64MEM module.
+	pub fn ram(&self) -> anyhow::Result<&[u8]> {
+		let Some(m) = self.module("C64MEM") else {
+			anyhow::bail!("No C64MEM module in snapshot");
+		};
+		let mut r = Reader::new(&m.data);
+		r.bytes(4)?; // cpu port data and direction, exrom, game
+		Ok(r.bytes(RAM_SIZE)?)
+	}
+
+	/// Value and direction of the CPU port at $01 and $00.
+	pub fn cpu_port(&self) -> anyhow::Result<(u8, u8)> {
+		let Some(m) = self.module("C64MEM") else {
+			anyhow::bail!("No C64MEM module in snapshot");
+		};
+		let mut r = Reader::new(&m.data);
+		Ok((r.u8()?, r.u8()?))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn name(name: &str) -> Vec<u8> {
+		let mut bytes = name.as_bytes().to_vec();
+		bytes.resize(NAME_LEN, 0);
+		bytes
+	}
+
+	fn module(module_name: &str, version: (u8, u8), data: &[u8]) -> Vec<u8> {
+		let mut bytes = name(module_name);
+		bytes.extend_from_slice(&[version.0, version.1]);
+		bytes.extend_from_slice(&((MODULE_HEADER_LEN + data.len()) as u32).to_le_bytes());
+		bytes.extend_from_slice(data);
+		bytes
+	}
+
+	fn header(with_vice_version: bool) -> Vec<u8> {
+		let mut bytes = MAGIC.to_vec();
+		bytes.extend_from_slice(&[2, 0]);
+		bytes.extend_from_slice(&name("C64SC"));
+		if with_vice_version {
+			bytes.extend_from_slice(VERSION_MAGIC);
+			bytes.extend_from_slice(&[3, 7, 1, 0]);
+			bytes.extend_from_slice(&42u32.to_le_bytes());
+		}
+		bytes
+	}
+
+	fn snapshot() -> Vec<u8> {
+		let mut bytes = header(true);
+		// clock, a, x, y, sp, pc, status, last opcode info
+		let mut cpu = 0x12345678u32.to_le_bytes().to_vec();
+		cpu.extend_from_slice(&[0x01, 0x02, 0x03, 0xf6, 0x0d, 0x08, 0x24, 0, 0, 0, 0]);
+		bytes.extend_from_slice(&module("MAINCPU", (1, 1), &cpu));
+		let mut mem = vec![0x37, 0x2f, 0x01, 0x01];
+		let mut ram = vec![0u8; RAM_SIZE];
+		ram[0x0400] = 0x12;
+		ram[0xffff] = 0xff;
+		mem.extend_from_slice(&ram);
+		mem.extend_from_slice(&[0; 8]); // more fields follow the RAM
+		bytes.extend_from_slice(&module("C64MEM", (0, 1), &mem));
+		bytes.extend_from_slice(&module("VIC-II", (1, 1), &[0; 16]));
+		bytes.extend_from_slice(&module("CIA1", (2, 2), &[0; 8]));
+		bytes.extend_from_slice(&module("SID", (1, 3), &[0; 4]));
+		bytes
+	}
+
+	#[test]
+	fn modules() {
+		let vsf = Vsf::parse(&snapshot()).unwrap();
+		assert_eq!(vsf.version(), (2, 0));
+		assert_eq!(vsf.machine(), "C64SC");
+		assert_eq!(vsf.vice_version(), Some((3, 7, 1, 0)));
+		let names = vsf.modules().iter().map(|m| m.name()).collect::<Vec<_>>();
+		assert_eq!(names, ["MAINCPU", "C64MEM", "VIC-II", "CIA1", "SID"]);
+		assert_eq!(vsf.module("CIA1").unwrap().version(), (2, 2));
+		assert_eq!(vsf.module("SID").unwrap().data().len(), 4);
+	}
+
+	#[test]
+	fn registers() {
+		let registers = Vsf::parse(&snapshot()).unwrap().registers().unwrap();
+		assert_eq!(registers.clock(), 0x12345678);
+		assert_eq!(
+			(registers.a(), registers.x(), registers.y(), registers.sp()),
+			(0x01, 0x02, 0x03, 0xf6)
+		);
+		assert_eq!(registers.pc(), 0x080d);
+		assert_eq!(registers.status(), 0x24);
+	}
+
+	#[test]
+	fn ram() {
+		let vsf = Vsf::parse(&snapshot()).unwrap();
+		let ram = vsf.ram().unwrap();
+		assert_eq!(ram.len(), RAM_SIZE);
+		assert_eq!((ram[0x0400], ram[0xffff]), (0x12, 0xff));
+		assert_eq!(vsf.cpu_port().unwrap(), (0x37, 0x2f));
+	}
+
+	#[test]
+	fn without_vice_version() {
+		let mut bytes = header(false);
+		bytes.extend_from_slice(&module("SID", (1, 3), &[1, 2]));
+		let vsf = Vsf::parse(&bytes).unwrap();
+		assert_eq!(vsf.vice_version(), None);
+		assert_eq!(vsf.modules()[0].data(), &[1, 2]);
+		assert!(vsf.registers().is_err());
+	}
+
+	#[test]
+	fn invalid() {
+		assert!(Vsf::parse(b"not a snapshot").is_err());
+		let mut truncated = snapshot();
+		truncated.pop();
+		assert!(Vsf::parse(&truncated).is_err());
+	}
+}